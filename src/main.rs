@@ -2,6 +2,7 @@ use clap::{Parser, Subcommand};
 use nostr_sdk::Result;
 
 mod sub_commands;
+mod timeline;
 mod utils;
 
 /// Simple CLI application to interact with nostr
@@ -38,8 +39,22 @@ enum Commands {
     DeleteProfile(sub_commands::delete_profile::DeleteProfileSubCommand),
     /// React to an event
     React(sub_commands::react::ReactionSubCommand),
+    /// Repost an event (NIP-18)
+    Repost(sub_commands::repost::RepostSubCommand),
     /// Get all events
-    ListEvents(sub_commands::list_events::ListEventsSubCommand),
+    ListEvents(Box<sub_commands::list_events::ListEventsSubCommand>),
+    /// Count events matching a filter on each relay (NIP-45)
+    Count(sub_commands::count::CountSubCommand),
+    /// Reconcile events between relays and a local event file with negentropy (NIP-77)
+    Sync(sub_commands::sync::SyncSubCommand),
+    /// Show the conversation tree a note belongs to
+    Thread(sub_commands::thread::ThreadSubCommand),
+    /// Keep a local archive of an account's events
+    Archive(sub_commands::archive::ArchiveSubCommand),
+    /// Copy events matching a filter from one set of relays to another
+    Mirror(sub_commands::mirror::MirrorSubCommand),
+    /// Search for user profiles (NIP-50)
+    SearchUsers(sub_commands::search_users::SearchUsersSubCommand),
     /// Generate a new keypair
     GenerateKeypair(sub_commands::generate_keypair::GenerateKeypairSubCommand),
     /// Convert key from bech32 to hex or hex to bech32
@@ -70,6 +85,8 @@ enum Commands {
     CustomEvent(sub_commands::custom_event::CustomEventCommand),
     /// Create a user status event
     SetUserStatus(sub_commands::user_status::UserStatusSubCommand),
+    /// Run operations from a YAML or JSON script over a single relay connection
+    Batch(sub_commands::batch::BatchSubCommand),
 }
 
 #[tokio::main]
@@ -135,9 +152,36 @@ async fn main() -> Result<()> {
             )
             .await
         }
+        Commands::Repost(sub_command_args) => {
+            sub_commands::repost::repost(
+                args.private_key,
+                args.relays,
+                args.difficulty_target,
+                sub_command_args,
+            )
+            .await
+        }
         Commands::ListEvents(sub_command_args) => {
             sub_commands::list_events::list_events(args.relays, sub_command_args).await
         }
+        Commands::Count(sub_command_args) => {
+            sub_commands::count::count(args.relays, sub_command_args).await
+        }
+        Commands::Sync(sub_command_args) => {
+            sub_commands::sync::sync(args.relays, sub_command_args).await
+        }
+        Commands::Thread(sub_command_args) => {
+            sub_commands::thread::thread(args.relays, sub_command_args).await
+        }
+        Commands::Archive(sub_command_args) => {
+            sub_commands::archive::archive(args.relays, sub_command_args).await
+        }
+        Commands::Mirror(sub_command_args) => {
+            sub_commands::mirror::mirror(args.relays, sub_command_args).await
+        }
+        Commands::SearchUsers(sub_command_args) => {
+            sub_commands::search_users::search_users(args.relays, sub_command_args).await
+        }
         Commands::GenerateKeypair(sub_command_args) => {
             sub_commands::generate_keypair::get_new_keypair(sub_command_args).await
         }
@@ -238,5 +282,14 @@ async fn main() -> Result<()> {
             )
             .await
         }
+        Commands::Batch(sub_command_args) => {
+            sub_commands::batch::batch(
+                args.private_key,
+                args.relays,
+                args.difficulty_target,
+                sub_command_args,
+            )
+            .await
+        }
     }
 }
@@ -0,0 +1,221 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use clap::{Args, Subcommand};
+use nostr_sdk::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::sub_commands::list_events::{
+    add_relay_hints, fetch_all_events, incomplete_history_error,
+};
+use crate::utils::{
+    create_client, fetch_relay_list, parse_public_key_with_hints, read_events_file,
+};
+
+const EVENTS_FILE: &str = "events.jsonl";
+const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Args)]
+pub struct ArchiveSubCommand {
+    #[command(subcommand)]
+    command: ArchiveCommands,
+}
+
+#[derive(Subcommand)]
+enum ArchiveCommands {
+    /// Export every event of an author to a JSONL archive. Running it again only fetches new events.
+    Export(ArchiveExportArgs),
+}
+
+#[derive(Args)]
+struct ArchiveExportArgs {
+    /// Author to archive. Hex, npub, nprofile and NIP-05 identifiers are supported.
+    #[arg(short, long)]
+    author: String,
+    /// Directory the archive is written to
+    #[arg(short, long, default_value = "archive")]
+    directory: String,
+    /// Also archive events addressed to the author (p-tagged)
+    #[arg(long, default_value = "false")]
+    include_mentions: bool,
+    /// Timeout in seconds
+    #[arg(long, default_value_t = 30)]
+    timeout: u64,
+}
+
+// Describes the archive so the next export can continue where this one stopped
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    author: String,
+    include_mentions: bool,
+    /// Unix timestamp of when the last export started
+    last_export: u64,
+    event_count: usize,
+    relays: Vec<String>,
+    /// Per relay, unix timestamp of when the last export that got its whole history started
+    #[serde(default)]
+    cursors: BTreeMap<String, u64>,
+}
+
+impl Manifest {
+    // Manifests written before per relay cursors existed only have a single timestamp
+    fn relay_cursors(&self) -> BTreeMap<String, u64> {
+        if !self.cursors.is_empty() {
+            return self.cursors.clone();
+        }
+        self.relays
+            .iter()
+            .map(|relay| (relay.clone(), self.last_export))
+            .collect()
+    }
+}
+
+pub async fn archive(relays: Vec<String>, sub_command_args: &ArchiveSubCommand) -> Result<()> {
+    match &sub_command_args.command {
+        ArchiveCommands::Export(args) => export(relays, args).await,
+    }
+}
+
+async fn export(relays: Vec<String>, args: &ArchiveExportArgs) -> Result<()> {
+    if relays.is_empty() {
+        panic!("No relays specified, at least one relay is required!")
+    }
+
+    let started_at = Timestamp::now();
+    let (author, relay_hints) = parse_public_key_with_hints(&args.author).await?;
+    let timeout = Some(Duration::from_secs(args.timeout));
+
+    std::fs::create_dir_all(&args.directory)?;
+    let events_path = Path::new(&args.directory).join(EVENTS_FILE);
+    let manifest_path = Path::new(&args.directory).join(MANIFEST_FILE);
+
+    let manifest: Option<Manifest> = if manifest_path.exists() {
+        Some(serde_json::from_reader(std::fs::File::open(
+            &manifest_path,
+        )?)?)
+    } else {
+        None
+    };
+    if let Some(manifest) = &manifest {
+        if manifest.author != author.to_hex() {
+            return Err(format!(
+                "{} is an archive of {}, not of {}",
+                args.directory, manifest.author, args.author
+            )
+            .into());
+        }
+    }
+
+    let (archived_events, _) = read_events_file(&events_path.to_string_lossy())?;
+    let mut archived_ids: HashSet<EventId> = archived_events.iter().map(|event| event.id).collect();
+
+    let client = create_client(&Keys::generate(), relays, 0).await?;
+    add_relay_hints(&client, relay_hints).await;
+
+    // Also query the relays the author lists in their relay list (NIP-65)
+    let urls: Vec<String> = fetch_relay_list(&client, author, timeout)
+        .await?
+        .into_iter()
+        .map(|(url, _)| url)
+        .collect();
+    if !urls.is_empty() {
+        println!(
+            "Adding {} relay(s) from the author's relay list",
+            urls.len()
+        );
+        add_relay_hints(&client, urls).await;
+    }
+
+    let mut archive_relays: Vec<String> = client
+        .relays()
+        .await
+        .into_keys()
+        .map(|url| url.to_string())
+        .collect();
+    archive_relays.sort();
+    client.disconnect().await?;
+
+    let include_mentions = args.include_mentions
+        || manifest
+            .as_ref()
+            .is_some_and(|manifest| manifest.include_mentions);
+    let mut filters = vec![Filter::new().author(author)];
+    if include_mentions {
+        filters.push(Filter::new().pubkey(author));
+    }
+
+    // Each relay continues from its own cursor, so relays that failed or were added since the
+    // last export get their whole history. Duplicates are skipped below.
+    let mut cursors = manifest
+        .as_ref()
+        .map(|manifest| manifest.relay_cursors())
+        .unwrap_or_default();
+    let mut events: Vec<Event> = Vec::new();
+    let mut incomplete: Vec<Url> = Vec::new();
+    for url in archive_relays.iter() {
+        let relay_filters: Vec<Filter> = match cursors.get(url) {
+            Some(since) => filters
+                .iter()
+                .map(|filter| filter.clone().since(Timestamp::from(*since)))
+                .collect(),
+            None => filters.clone(),
+        };
+        let client = create_client(&Keys::generate(), vec![url.clone()], 0).await?;
+        let (relay_events, relay_incomplete) =
+            fetch_all_events(&client, relay_filters, timeout).await;
+        client.disconnect().await?;
+
+        events.extend(relay_events);
+        if relay_incomplete.is_empty() {
+            cursors.insert(url.clone(), started_at.as_u64());
+        } else {
+            incomplete.extend(relay_incomplete);
+        }
+    }
+    events.sort_by_key(|event| event.created_at);
+
+    let mut invalid = 0;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&events_path)?;
+    let mut added = 0;
+    for event in events.iter() {
+        if event.verify().is_err() {
+            invalid += 1;
+            continue;
+        }
+        if archived_ids.insert(event.id) {
+            writeln!(file, "{}", event.as_json())?;
+            added += 1;
+        }
+    }
+
+    let manifest = Manifest {
+        author: author.to_hex(),
+        include_mentions,
+        last_export: started_at.as_u64(),
+        event_count: archived_ids.len(),
+        relays: archive_relays,
+        cursors,
+    };
+    serde_json::to_writer_pretty(std::fs::File::create(&manifest_path)?, &manifest)?;
+
+    if invalid > 0 {
+        eprintln!("Skipped {invalid} event(s) with an invalid signature");
+    }
+    println!(
+        "Added {added} new event(s), the archive in {} now has {} event(s)",
+        args.directory,
+        archived_ids.len()
+    );
+
+    // The relays that failed are fetched from their previous cursor on the next export
+    if !incomplete.is_empty() {
+        return Err(incomplete_history_error(&incomplete));
+    }
+
+    Ok(())
+}
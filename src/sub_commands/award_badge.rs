@@ -1,17 +1,19 @@
-use std::{process::exit, str::FromStr, time::Duration};
+use std::{str::FromStr, time::Duration};
 
 use clap::Args;
 use nostr_sdk::prelude::*;
+use serde::Deserialize;
 
 use crate::utils::{create_client, parse_private_key};
 
-#[derive(Args)]
+#[derive(Args, Deserialize)]
 pub struct AwardBadgeSubCommand {
     /// Badge definition event id
     #[arg(short, long)]
     badge_event_id: String,
     /// Awarded pubkeys
     #[arg(short, long, action = clap::ArgAction::Append)]
+    #[serde(default)]
     ptag: Vec<String>,
 }
 
@@ -28,6 +30,21 @@ pub async fn award_badge(
     let keys = parse_private_key(private_key, true).await?;
     let client: Client = create_client(&keys, relays, difficulty_target).await?;
 
+    let event_id = send_badge_award(&client, &keys, difficulty_target, sub_command_args).await?;
+
+    println!("Published badge award event with id:");
+    println!("Hex: {}", event_id.to_hex());
+    println!("Bech32: {}", event_id.to_bech32()?);
+
+    Ok(())
+}
+
+pub async fn send_badge_award(
+    client: &Client,
+    keys: &Keys,
+    difficulty_target: u8,
+    sub_command_args: &AwardBadgeSubCommand,
+) -> Result<EventId> {
     let event_id: EventId = EventId::from_str(sub_command_args.badge_event_id.as_str())?;
     let badge_definition_query = client
         .get_events_of(
@@ -37,46 +54,36 @@ pub async fn award_badge(
         .await?;
 
     if badge_definition_query.len() != 1 {
-        eprintln!("Expected one event, got {}", badge_definition_query.len());
-        exit(1)
+        return Err(format!("Expected one event, got {}", badge_definition_query.len()).into());
     };
 
     let badge_definition_event = badge_definition_query.first().unwrap();
     // Verify that this event is a badge definition event
     if badge_definition_event.kind != Kind::BadgeDefinition {
-        eprintln!(
+        return Err(format!(
             "Unexpected badge definition event. Exepected event of kind {} but got {}",
             Kind::BadgeDefinition.as_u32(),
             badge_definition_event.kind.as_u32()
-        );
-        exit(1)
+        )
+        .into());
     }
 
     // Verify that the user trying to award the badge is actually the author of the badge definition
     if badge_definition_event.pubkey != keys.public_key() {
-        eprint!("Incorrect private key. Only the private key used for issuing the badge definition can award it to other public keys");
-        exit(1)
+        return Err("Incorrect private key. Only the private key used for issuing the badge definition can award it to other public keys".into());
     }
 
     let awarded_pubkeys: Vec<Tag> = sub_command_args
         .ptag
         .iter()
-        .map(|pubkey_string| {
-            Tag::public_key(
-                public_key::PublicKey::from_str(pubkey_string).expect("Unable to parse public key"),
-            )
-        })
-        .collect();
+        .map(|pubkey_string| Ok(Tag::public_key(PublicKey::from_str(pubkey_string)?)))
+        .collect::<Result<Vec<Tag>>>()?;
 
     let event = EventBuilder::award_badge(badge_definition_event, awarded_pubkeys)?
-        .to_pow_event(&keys, difficulty_target)?;
+        .to_pow_event(keys, difficulty_target)?;
 
     // Publish event
     let event_id = client.send_event(event).await?;
 
-    println!("Published badge award event with id:");
-    println!("Hex: {}", event_id.to_hex());
-    println!("Bech32: {}", event_id.to_bech32()?);
-
-    Ok(())
+    Ok(event_id)
 }
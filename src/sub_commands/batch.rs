@@ -0,0 +1,209 @@
+use std::path::Path;
+
+use clap::Args;
+use nostr_sdk::prelude::*;
+use serde::Deserialize;
+
+use crate::sub_commands::award_badge::{send_badge_award, AwardBadgeSubCommand};
+use crate::sub_commands::create_badge::{send_badge_definition, CreateBadgeSubCommand};
+use crate::sub_commands::custom_event::{send_custom_event, CustomEventCommand};
+use crate::sub_commands::delete_event::{send_deletion, DeleteEventSubCommand};
+use crate::sub_commands::profile_badges::{send_profile_badges, ProfileBadgesSubCommand};
+use crate::sub_commands::react::{send_reaction, ReactionSubCommand};
+use crate::sub_commands::repost::{send_repost, RepostSubCommand};
+use crate::sub_commands::set_metadata::{send_metadata, SetMetadataSubCommand};
+use crate::sub_commands::text_note::{send_textnote, TextNoteSubCommand};
+use crate::sub_commands::user_status::{send_user_status, UserStatusSubCommand};
+use crate::utils::{create_client, parse_private_key};
+
+#[derive(Args)]
+pub struct BatchSubCommand {
+    /// Path to a YAML (.yaml/.yml) or JSON file containing a list of operations.
+    /// Each operation has a "command" field with the subcommand name, e.g. "text-note",
+    /// and the same fields as the subcommand's arguments, e.g. "content".
+    #[arg(short, long)]
+    file_path: String,
+    /// Stop executing the remaining operations after the first failure
+    #[arg(long, default_value = "false")]
+    fail_fast: bool,
+}
+
+/// A single step in a batch script
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+enum BatchOperation {
+    SetMetadata(SetMetadataSubCommand),
+    TextNote(TextNoteSubCommand),
+    DeleteEvent(DeleteEventSubCommand),
+    React(ReactionSubCommand),
+    Repost(RepostSubCommand),
+    CreateBadge(CreateBadgeSubCommand),
+    AwardBadge(AwardBadgeSubCommand),
+    ProfileBadges(ProfileBadgesSubCommand),
+    CustomEvent(CustomEventCommand),
+    SetUserStatus(UserStatusSubCommand),
+}
+
+impl BatchOperation {
+    fn name(&self) -> &'static str {
+        match self {
+            BatchOperation::SetMetadata(_) => "set-metadata",
+            BatchOperation::TextNote(_) => "text-note",
+            BatchOperation::DeleteEvent(_) => "delete-event",
+            BatchOperation::React(_) => "react",
+            BatchOperation::Repost(_) => "repost",
+            BatchOperation::CreateBadge(_) => "create-badge",
+            BatchOperation::AwardBadge(_) => "award-badge",
+            BatchOperation::ProfileBadges(_) => "profile-badges",
+            BatchOperation::CustomEvent(_) => "custom-event",
+            BatchOperation::SetUserStatus(_) => "set-user-status",
+        }
+    }
+
+    async fn execute(
+        &self,
+        client: &Client,
+        keys: &Keys,
+        difficulty_target: u8,
+    ) -> Result<EventId> {
+        match self {
+            BatchOperation::SetMetadata(args) => {
+                send_metadata(client, keys, difficulty_target, args).await
+            }
+            BatchOperation::TextNote(args) => send_textnote(client, args).await,
+            BatchOperation::DeleteEvent(args) => send_deletion(client, args).await,
+            BatchOperation::React(args) => send_reaction(client, args).await,
+            BatchOperation::Repost(args) => send_repost(client, args).await,
+            BatchOperation::CreateBadge(args) => {
+                send_badge_definition(client, keys, difficulty_target, args).await
+            }
+            BatchOperation::AwardBadge(args) => {
+                send_badge_award(client, keys, difficulty_target, args).await
+            }
+            BatchOperation::ProfileBadges(args) => {
+                send_profile_badges(client, keys, difficulty_target, args).await
+            }
+            BatchOperation::CustomEvent(args) => {
+                send_custom_event(client, keys, difficulty_target, args).await
+            }
+            BatchOperation::SetUserStatus(args) => {
+                send_user_status(client, keys, difficulty_target, args).await
+            }
+        }
+    }
+}
+
+fn read_operations(file_path: &str) -> Result<Vec<BatchOperation>> {
+    let content = std::fs::read_to_string(file_path)?;
+    let yaml = matches!(
+        Path::new(file_path)
+            .extension()
+            .and_then(|ext| ext.to_str()),
+        Some("yaml") | Some("yml")
+    );
+    parse_operations(&content, yaml)
+}
+
+fn parse_operations(content: &str, yaml: bool) -> Result<Vec<BatchOperation>> {
+    let operations = if yaml {
+        serde_yaml::from_str(content)?
+    } else {
+        serde_json::from_str(content)?
+    };
+    Ok(operations)
+}
+
+pub async fn batch(
+    private_key: Option<String>,
+    relays: Vec<String>,
+    difficulty_target: u8,
+    sub_command_args: &BatchSubCommand,
+) -> Result<()> {
+    if relays.is_empty() {
+        panic!("No relays specified, at least one relay is required!")
+    }
+
+    // Parse the whole script up front so a malformed file doesn't publish anything
+    let operations = read_operations(&sub_command_args.file_path)?;
+
+    let keys = parse_private_key(private_key, true).await?;
+    let client = create_client(&keys, relays, difficulty_target).await?;
+
+    let mut results: Vec<(&'static str, Result<EventId>)> = Vec::new();
+    for operation in operations.iter() {
+        let result = operation.execute(&client, &keys, difficulty_target).await;
+        let failed = result.is_err();
+        results.push((operation.name(), result));
+        if failed && sub_command_args.fail_fast {
+            break;
+        }
+    }
+
+    println!("Batch report:");
+    let mut succeeded = 0;
+    for (step, (name, result)) in results.iter().enumerate() {
+        match result {
+            Ok(event_id) => {
+                succeeded += 1;
+                println!(
+                    "{:>4}. {:<16} ok      {}",
+                    step + 1,
+                    name,
+                    event_id.to_bech32()?
+                );
+            }
+            Err(e) => println!("{:>4}. {:<16} failed  {}", step + 1, name, e),
+        }
+    }
+    let failed = results.len() - succeeded;
+    let skipped = operations.len() - results.len();
+    println!("{succeeded} succeeded, {failed} failed, {skipped} skipped");
+
+    if failed > 0 {
+        return Err(format!("{failed} batch operation(s) failed").into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_operations() {
+        let yaml = "
+- command: text-note
+  content: hi
+  expiration: 3600
+- command: text-note
+  content: later
+  expiration: 2h
+- command: set-user-status
+  content: away
+  expiration: 2024-03-16
+- command: repost
+  event: note1h445ule4je70k7kvddate8kpsh2fd6n77esevww5hmgda2qwssjsw957wk
+";
+        let operations = parse_operations(yaml, true).unwrap();
+        let names: Vec<&str> = operations
+            .iter()
+            .map(|operation| operation.name())
+            .collect();
+        assert_eq!(
+            names,
+            ["text-note", "text-note", "set-user-status", "repost"]
+        );
+
+        let json = r#"[
+            {"command": "text-note", "content": "hi", "expiration": 3600},
+            {"command": "set-user-status", "content": "away", "expiration": 3600},
+            {"command": "text-note", "content": "no expiration"}
+        ]"#;
+        assert_eq!(parse_operations(json, false).unwrap().len(), 3);
+
+        assert!(parse_operations("- command: unknown-command\n", true).is_err());
+        let json = r#"[{"command": "text-note", "content": "hi", "expiration": [1]}]"#;
+        assert!(parse_operations(json, false).is_err());
+    }
+}
@@ -1,13 +1,74 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
 use clap::Args;
 use nostr_sdk::prelude::*;
+use serde::de::{SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer as _, Serialize};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::time::MissedTickBehavior;
+
+use crate::utils::{create_client_without_signer, parse_public_key_with_hints};
+
+// Number of parsed events buffered ahead of the publisher
+const EVENT_BUFFER: usize = 1024;
+const STATE_SAVE_INTERVAL: Duration = Duration::from_secs(1);
+// Machine-readable prefixes relays use in OK messages when refusing an event (NIP-01)
+const REJECTION_PREFIXES: [&str; 6] = [
+    "blocked:",
+    "rate-limited:",
+    "invalid:",
+    "pow:",
+    "restricted:",
+    "error:",
+];
+const CSV_REQUIRED_COLUMNS: [&str; 7] = [
+    "id",
+    "pubkey",
+    "created_at",
+    "kind",
+    "tags",
+    "content",
+    "sig",
+];
 
-use crate::utils::{create_client, parse_private_key};
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum InputFormat {
+    /// A JSON array of events
+    Json,
+    /// One JSON event per line
+    Jsonl,
+    /// CSV as written by list-events with --format csv --columns id,pubkey,created_at,kind,tags,content,sig.
+    /// The default list-events columns are not enough to rebuild signed events.
+    Csv,
+}
 
 #[derive(Args)]
 pub struct BroadcastEventsSubCommand {
-    /// Input file path, should contain an array of JSON events
+    /// Input file path, should contain a JSON array of events, JSON lines or CSV. Use - to read from stdin.
     #[arg(short, long)]
     file_path: String,
+    /// Input format, detected from the content when omitted
+    #[arg(long, value_enum)]
+    format: Option<InputFormat>,
+    /// Maximum number of events published per second
+    #[arg(long)]
+    rate: Option<f64>,
+    /// Number of events published at the same time
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    concurrency: u32,
+    /// File to keep track of the progress in, an interrupted broadcast resumes from it
+    #[arg(long)]
+    state_file: Option<String>,
+    /// Only broadcast events of these kinds
+    #[arg(short, long, action = clap::ArgAction::Append)]
+    kinds: Option<Vec<u64>>,
+    /// Only broadcast events of these authors. Hex, npub, nprofile and NIP-05 identifiers are supported.
+    #[arg(short, long, action = clap::ArgAction::Append)]
+    authors: Option<Vec<String>>,
 }
 
 pub async fn broadcast_events(
@@ -17,19 +78,553 @@ pub async fn broadcast_events(
     if relays.is_empty() {
         panic!("No relays specified, at least one relay is required!")
     }
+    if sub_command_args.rate.is_some_and(|rate| rate <= 0.0) {
+        return Err("The rate has to be greater than zero".into());
+    }
+
+    let state = match &sub_command_args.state_file {
+        Some(state_file) if std::path::Path::new(state_file).exists() => {
+            let state: BroadcastState = serde_json::from_reader(std::fs::File::open(state_file)?)?;
+            if state.file_path != sub_command_args.file_path {
+                return Err(
+                    format!("{state_file} belongs to a broadcast of {}", state.file_path).into(),
+                );
+            }
+            println!("Resuming after {} event(s)", state.processed);
+            state
+        }
+        _ => BroadcastState {
+            file_path: sub_command_args.file_path.clone(),
+            ..Default::default()
+        },
+    };
+    let skip = state.processed;
+
+    let mut validator = Validator {
+        kinds: sub_command_args
+            .kinds
+            .as_ref()
+            .map(|kinds| kinds.iter().map(|kind| Kind::from(*kind as u16)).collect()),
+        authors: None,
+    };
+    if let Some(authors) = &sub_command_args.authors {
+        let mut public_keys: HashSet<PublicKey> = HashSet::new();
+        for author in authors.iter() {
+            let (public_key, _) = parse_public_key_with_hints(author).await?;
+            public_keys.insert(public_key);
+        }
+        validator.authors = Some(public_keys);
+    }
+
+    let mut reader: Box<dyn BufRead + Send> = if sub_command_args.file_path == "-" {
+        Box::new(BufReader::new(std::io::stdin()))
+    } else {
+        Box::new(BufReader::new(std::fs::File::open(
+            &sub_command_args.file_path,
+        )?))
+    };
+    let format = match sub_command_args.format {
+        Some(format) => format,
+        None => detect_format(&mut reader)?,
+    };
+
+    // Events are already signed, so no keys are needed to broadcast them
+    let client = create_client_without_signer(relays).await?;
+    let relays: Arc<Vec<(Url, Relay)>> = Arc::new(client.relays().await.into_iter().collect());
+
+    // Parse on a blocking thread so large files are never held in memory at once
+    let (sender, mut receiver) = mpsc::channel(EVENT_BUFFER);
+    let reader_task = tokio::task::spawn_blocking(move || {
+        if let Err(e) = read_events(reader, format, &sender) {
+            let _ = sender.blocking_send(Err(e));
+        }
+    });
 
-    let keys = parse_private_key(None, true).await?;
-    let client = create_client(&keys, relays.clone(), 0).await?;
+    let progress = Arc::new(Mutex::new(Progress {
+        state,
+        completed: BTreeSet::new(),
+        state_file: sub_command_args.state_file.clone(),
+        last_saved: Instant::now(),
+    }));
+    let concurrency = sub_command_args.concurrency;
+    let semaphore = Arc::new(Semaphore::new(concurrency as usize));
+    let mut interval = sub_command_args.rate.map(|rate| {
+        let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / rate));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    });
 
-    let file = std::fs::File::open(&sub_command_args.file_path)?;
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut position: u64 = 0;
+    let mut interrupted = false;
+    let mut read_error: Option<String> = None;
+    loop {
+        let event = tokio::select! {
+            _ = &mut ctrl_c => {
+                interrupted = true;
+                break;
+            }
+            event = receiver.recv() => match event {
+                Some(Ok(event)) => event,
+                Some(Err(e)) => {
+                    // Returned once the events in flight are published and the state is saved
+                    read_error = Some(e);
+                    break;
+                }
+                None => break,
+            },
+        };
+        let event_position = position;
+        position += 1;
+        if event_position < skip {
+            continue;
+        }
+        if let Some(reason) = validator.skip_reason(&event) {
+            eprintln!("Skipping {}: {reason}", event.id);
+            progress.lock().unwrap().skip(event_position, reason);
+            continue;
+        }
 
-    let events: Vec<Event> = serde_json::from_reader(file)?;
+        if let Some(interval) = interval.as_mut() {
+            interval.tick().await;
+        }
+        let permit = semaphore.clone().acquire_owned().await?;
+        let relays = relays.clone();
+        let progress = progress.clone();
+        tokio::spawn(async move {
+            let outcomes = publish(&relays, event).await;
+            progress.lock().unwrap().record(event_position, outcomes);
+            drop(permit);
+        });
+    }
 
-    for event in events.clone() {
-        client.send_event(event).await?;
+    // Wait for the events that are still being published
+    let _ = semaphore.acquire_many(concurrency).await?;
+    if !interrupted {
+        reader_task.await?;
     }
 
-    println!("Published {} events to {:?}", events.len(), relays);
+    let progress = progress.lock().unwrap();
+    progress.save()?;
+    println!("Processed {} event(s)", progress.state.processed);
+    for (reason, count) in progress.state.skipped.iter() {
+        println!("Skipped {count} event(s): {reason}");
+    }
+    for (url, tally) in progress.state.relays.iter() {
+        println!(
+            "{url}: {} accepted, {} duplicate, {} rejected, {} failed",
+            tally.accepted, tally.duplicate, tally.rejected, tally.failed
+        );
+    }
+    if interrupted {
+        match &progress.state_file {
+            Some(state_file) => {
+                println!("Interrupted, run again with --state-file {state_file} to resume")
+            }
+            None => println!("Interrupted, use --state-file to be able to resume a broadcast"),
+        }
+    }
+    if let Some(e) = read_error {
+        return Err(e.into());
+    }
 
     Ok(())
 }
+
+// Sends the event to every relay and waits for their OK messages
+async fn publish(relays: &[(Url, Relay)], event: Event) -> Vec<(String, Outcome)> {
+    let mut outcomes: Vec<(String, Outcome)> = Vec::with_capacity(relays.len());
+    for (url, relay) in relays.iter() {
+        let outcome = match relay
+            .send_event(event.clone(), RelaySendOptions::new())
+            .await
+        {
+            Ok(_) => Outcome::Accepted,
+            Err(e) => {
+                let message = e.to_string();
+                if message.contains("duplicate:") {
+                    Outcome::Duplicate
+                } else if REJECTION_PREFIXES
+                    .iter()
+                    .any(|prefix| message.contains(prefix))
+                {
+                    eprintln!("{url}: rejected {}: {message}", event.id);
+                    Outcome::Rejected
+                } else {
+                    eprintln!("{url}: failed to publish {}: {message}", event.id);
+                    Outcome::Failed
+                }
+            }
+        };
+        outcomes.push((url.to_string(), outcome));
+    }
+    outcomes
+}
+
+enum Outcome {
+    Accepted,
+    Duplicate,
+    Rejected,
+    Failed,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct RelayTally {
+    accepted: u64,
+    duplicate: u64,
+    rejected: u64,
+    failed: u64,
+}
+
+// Saved to the state file so an interrupted broadcast can be resumed
+#[derive(Default, Serialize, Deserialize)]
+struct BroadcastState {
+    file_path: String,
+    /// Number of events from the start of the input that have been published
+    processed: u64,
+    relays: BTreeMap<String, RelayTally>,
+    #[serde(default)]
+    skipped: BTreeMap<String, u64>,
+}
+
+struct Progress {
+    state: BroadcastState,
+    // Positions published out of order, ahead of `state.processed`
+    completed: BTreeSet<u64>,
+    state_file: Option<String>,
+    last_saved: Instant,
+}
+
+impl Progress {
+    fn record(&mut self, position: u64, outcomes: Vec<(String, Outcome)>) {
+        for (url, outcome) in outcomes.into_iter() {
+            let tally = self.state.relays.entry(url).or_default();
+            match outcome {
+                Outcome::Accepted => tally.accepted += 1,
+                Outcome::Duplicate => tally.duplicate += 1,
+                Outcome::Rejected => tally.rejected += 1,
+                Outcome::Failed => tally.failed += 1,
+            }
+        }
+
+        self.complete(position);
+    }
+
+    fn skip(&mut self, position: u64, reason: &str) {
+        *self.state.skipped.entry(reason.to_string()).or_default() += 1;
+        self.complete(position);
+    }
+
+    fn complete(&mut self, position: u64) {
+        // Only a contiguous run of handled events can be skipped when resuming
+        self.completed.insert(position);
+        while self.completed.remove(&self.state.processed) {
+            self.state.processed += 1;
+        }
+
+        if self.last_saved.elapsed() >= STATE_SAVE_INTERVAL {
+            if let Err(e) = self.save() {
+                eprintln!("Unable to save the broadcast state: {e}");
+            }
+            self.last_saved = Instant::now();
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(state_file) = &self.state_file {
+            // Write a temporary file first, so a crash while saving doesn't corrupt the state
+            let tmp_file = format!("{state_file}.tmp");
+            let mut file = std::fs::File::create(&tmp_file)?;
+            serde_json::to_writer_pretty(&mut file, &self.state)?;
+            file.sync_all()?;
+            std::fs::rename(&tmp_file, state_file)?;
+        }
+        Ok(())
+    }
+}
+
+// Checks that decide which events are broadcast at all
+struct Validator {
+    kinds: Option<HashSet<Kind>>,
+    authors: Option<HashSet<PublicKey>>,
+}
+
+impl Validator {
+    // Returns why the event shouldn't be broadcast, if it shouldn't
+    fn skip_reason(&self, event: &Event) -> Option<&'static str> {
+        if event.verify_id().is_err() {
+            return Some("invalid id");
+        }
+        if event.verify_signature().is_err() {
+            return Some("invalid signature");
+        }
+        if event.is_expired() {
+            return Some("expired");
+        }
+        if let Some(kinds) = &self.kinds {
+            if !kinds.contains(&event.kind) {
+                return Some("kind not selected");
+            }
+        }
+        if let Some(authors) = &self.authors {
+            if !authors.contains(&event.pubkey) {
+                return Some("author not selected");
+            }
+        }
+        None
+    }
+}
+
+// Looks at the first non-whitespace character without consuming anything but whitespace
+fn detect_format(reader: &mut dyn BufRead) -> Result<InputFormat> {
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(InputFormat::Jsonl);
+        }
+        match buffer.iter().position(|byte| !byte.is_ascii_whitespace()) {
+            Some(position) => {
+                let format = match buffer[position] {
+                    b'[' => InputFormat::Json,
+                    b'{' => InputFormat::Jsonl,
+                    _ => InputFormat::Csv,
+                };
+                reader.consume(position);
+                return Ok(format);
+            }
+            None => {
+                let length = buffer.len();
+                reader.consume(length);
+            }
+        }
+    }
+}
+
+// Sends every event to the publisher as soon as it is parsed. Errors are returned as strings
+// since they have to cross the thread boundary.
+fn read_events(
+    reader: Box<dyn BufRead + Send>,
+    format: InputFormat,
+    sender: &mpsc::Sender<std::result::Result<Event, String>>,
+) -> std::result::Result<(), String> {
+    match format {
+        InputFormat::Json => {
+            let mut deserializer = serde_json::Deserializer::from_reader(reader);
+            deserializer
+                .deserialize_seq(EventSeqVisitor { sender })
+                .map_err(|e| format!("Invalid JSON array of events: {e}"))?;
+        }
+        InputFormat::Jsonl => {
+            for (number, line) in reader.lines().enumerate() {
+                let line = line.map_err(|e| e.to_string())?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let event = Event::from_json(&line)
+                    .map_err(|e| format!("line {}: invalid event: {e}", number + 1))?;
+                if sender.blocking_send(Ok(event)).is_err() {
+                    break;
+                }
+            }
+        }
+        InputFormat::Csv => {
+            let mut csv_reader = csv::Reader::from_reader(reader);
+            let headers = csv_reader.headers().map_err(|e| e.to_string())?.clone();
+            let missing: Vec<&str> = CSV_REQUIRED_COLUMNS
+                .into_iter()
+                .filter(|column| !headers.iter().any(|header| header == *column))
+                .collect();
+            if !missing.is_empty() {
+                return Err(format!(
+                    "CSV input is missing the columns {}, export it with list-events --format csv --columns {}",
+                    missing.join(","),
+                    CSV_REQUIRED_COLUMNS.join(",")
+                ));
+            }
+            let positions: Vec<usize> = CSV_REQUIRED_COLUMNS
+                .iter()
+                .filter_map(|column| headers.iter().position(|header| header == *column))
+                .collect();
+
+            for (number, record) in csv_reader.records().enumerate() {
+                let record = record.map_err(|e| e.to_string())?;
+                let event = csv_event(&record, &positions)
+                    .map_err(|e| format!("row {}: invalid event: {e}", number + 1))?;
+                if sender.blocking_send(Ok(event)).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Rebuilds an event from a CSV row, `positions` follows the order of CSV_REQUIRED_COLUMNS
+fn csv_event(record: &csv::StringRecord, positions: &[usize]) -> Result<Event> {
+    let field = |index: usize| record.get(positions[index]).unwrap_or_default();
+    let value = serde_json::json!({
+        "id": field(0),
+        "pubkey": field(1),
+        "created_at": field(2).parse::<u64>()?,
+        "kind": field(3).parse::<u64>()?,
+        "tags": serde_json::from_str::<Value>(field(4))?,
+        "content": field(5),
+        "sig": field(6),
+    });
+    Ok(serde_json::from_value(value)?)
+}
+
+// Streams the elements of a JSON array to the publisher instead of collecting them
+struct EventSeqVisitor<'a> {
+    sender: &'a mpsc::Sender<std::result::Result<Event, String>>,
+}
+
+impl<'de> Visitor<'de> for EventSeqVisitor<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of events")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> std::result::Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        while let Some(event) = seq.next_element::<Event>()? {
+            if self.sender.blocking_send(Ok(event)).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Detects the format like broadcast-events does and collects everything read_events sends
+    fn read_all(input: &str) -> (InputFormat, std::result::Result<Vec<Event>, String>) {
+        let mut reader: Box<dyn BufRead + Send> =
+            Box::new(std::io::Cursor::new(input.as_bytes().to_vec()));
+        let format = detect_format(&mut reader).unwrap();
+        let (sender, mut receiver) = mpsc::channel(16);
+        let result = read_events(reader, format, &sender);
+        drop(sender);
+
+        let mut events: Vec<Event> = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            events.push(event.unwrap());
+        }
+        (format, result.map(|_| events))
+    }
+
+    fn signed_events(count: usize) -> Vec<Event> {
+        let keys = Keys::generate();
+        (0..count)
+            .map(|i| {
+                EventBuilder::text_note(format!("note {i}, with a comma"), [Tag::hashtag("test")])
+                    .to_event(&keys)
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    fn csv_input(events: &[Event]) -> String {
+        let mut csv_writer = csv::Writer::from_writer(Vec::new());
+        csv_writer
+            .write_record([
+                "id",
+                "pubkey",
+                "created_at",
+                "kind",
+                "tags",
+                "content",
+                "sig",
+            ])
+            .unwrap();
+        for event in events.iter() {
+            csv_writer
+                .write_record([
+                    event.id.to_hex(),
+                    event.pubkey.to_hex(),
+                    event.created_at.as_u64().to_string(),
+                    event.kind.as_u32().to_string(),
+                    serde_json::to_string(&event.tags).unwrap(),
+                    event.content.clone(),
+                    event.sig.to_string(),
+                ])
+                .unwrap();
+        }
+        String::from_utf8(csv_writer.into_inner().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let detect = |input: &str| {
+            let mut reader = std::io::Cursor::new(input.as_bytes());
+            detect_format(&mut reader).unwrap()
+        };
+        assert_eq!(detect("[{}]"), InputFormat::Json);
+        assert_eq!(detect("\n  \t[]"), InputFormat::Json);
+        assert_eq!(detect("{\"id\":\"\"}\n"), InputFormat::Jsonl);
+        assert_eq!(detect("id,pubkey\n"), InputFormat::Csv);
+        assert_eq!(detect(""), InputFormat::Jsonl);
+        assert_eq!(detect(" \n "), InputFormat::Jsonl);
+    }
+
+    #[test]
+    fn test_read_json_array() {
+        let events = signed_events(3);
+        let (format, read) = read_all(&serde_json::to_string_pretty(&events).unwrap());
+        assert_eq!(format, InputFormat::Json);
+        assert_eq!(read.unwrap(), events);
+
+        let (_, read) = read_all("[]");
+        assert!(read.unwrap().is_empty());
+
+        // Events before a broken element have already been sent
+        let input = format!("[{}, {{\"id\": 1}}]", events[0].as_json());
+        assert!(read_all(&input).1.is_err());
+    }
+
+    #[test]
+    fn test_read_jsonl_with_blank_lines() {
+        let events = signed_events(2);
+        let input = format!(
+            "\n{}\n\n   \n{}\n\n",
+            events[0].as_json(),
+            events[1].as_json()
+        );
+        let (format, read) = read_all(&input);
+        assert_eq!(format, InputFormat::Jsonl);
+        assert_eq!(read.unwrap(), events);
+
+        let input = format!("{}\nnot an event\n", events[0].as_json());
+        let error = read_all(&input).1.unwrap_err();
+        assert!(error.starts_with("line 2: invalid event"));
+    }
+
+    #[test]
+    fn test_read_csv() {
+        let events = signed_events(2);
+        let (format, read) = read_all(&csv_input(&events));
+        assert_eq!(format, InputFormat::Csv);
+        assert_eq!(read.unwrap(), events);
+
+        // Leading whitespace before the header is skipped by the detection
+        let (format, read) = read_all(&format!("\n\n  {}", csv_input(&events)));
+        assert_eq!(format, InputFormat::Csv);
+        assert_eq!(read.unwrap(), events);
+    }
+
+    #[test]
+    fn test_read_csv_missing_columns() {
+        // The default list-events columns
+        let input = "id,pubkey,created_at,kind,content\n";
+        let error = read_all(input).1.unwrap_err();
+        assert!(error.contains("missing the columns tags,sig"));
+        assert!(error.contains("--columns id,pubkey,created_at,kind,tags,content,sig"));
+    }
+}
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use clap::Args;
+use nostr_sdk::prelude::*;
+
+use crate::sub_commands::list_events::{
+    add_relay_hints, build_filters, fetch_all_events, FilterArgs,
+};
+use crate::utils::create_client;
+
+#[derive(Args)]
+pub struct CountSubCommand {
+    #[command(flatten)]
+    filter_args: FilterArgs,
+    /// Fetch the events and count them locally on relays that don't support COUNT
+    #[arg(long, default_value = "false")]
+    fallback: bool,
+    /// Timeout in seconds
+    #[arg(long, default_value_t = 10)]
+    timeout: u64,
+}
+
+pub async fn count(relays: Vec<String>, sub_command_args: &CountSubCommand) -> Result<()> {
+    if relays.is_empty() {
+        panic!("No relays specified, at least one relay is required!")
+    }
+
+    let (filters, relay_hints) = build_filters(&sub_command_args.filter_args).await?;
+
+    let client = create_client(&Keys::generate(), relays, 0).await?;
+    add_relay_hints(&client, relay_hints).await;
+
+    let timeout = Duration::from_secs(sub_command_args.timeout);
+
+    let mut rows: Vec<(String, String)> = Vec::new();
+    for (url, relay) in client.relays().await.into_iter() {
+        let result = match relay.count_events_of(filters.clone(), timeout).await {
+            Ok(count) => count.to_string(),
+            Err(e) if sub_command_args.fallback => {
+                count_locally(&url, filters.clone(), timeout, &e.to_string()).await?
+            }
+            Err(e) => format!("COUNT not supported or failed: {e}"),
+        };
+        rows.push((url.to_string(), result));
+    }
+    rows.sort();
+
+    let width = rows
+        .iter()
+        .map(|(relay, _)| relay.len())
+        .max()
+        .unwrap_or(0)
+        .max("Relay".len());
+    println!("{:<width$}  Count", "Relay");
+    for (relay, result) in rows.iter() {
+        println!("{relay:<width$}  {result}");
+    }
+
+    Ok(())
+}
+
+// Pages through the whole matching history of the relay, a single request would stop at the
+// relay's limit. Only a lower bound can be given if the relay failed before the end.
+async fn count_locally(
+    url: &Url,
+    filters: Vec<Filter>,
+    timeout: Duration,
+    count_error: &str,
+) -> Result<String> {
+    let client = create_client(&Keys::generate(), vec![url.to_string()], 0).await?;
+    let (events, incomplete) = fetch_all_events(&client, filters, Some(timeout)).await;
+    client.disconnect().await?;
+
+    let result = if incomplete.is_empty() {
+        format!(
+            "{} (counted locally, COUNT failed: {count_error})",
+            events.len()
+        )
+    } else {
+        format!(
+            "at least {} (counted locally until fetching failed, COUNT failed: {count_error})",
+            events.len()
+        )
+    };
+    Ok(result)
+}
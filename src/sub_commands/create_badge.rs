@@ -1,9 +1,10 @@
 use clap::Args;
 use nostr_sdk::prelude::*;
+use serde::Deserialize;
 
 use crate::utils::{create_client, parse_private_key};
 
-#[derive(Args)]
+#[derive(Args, Deserialize)]
 pub struct CreateBadgeSubCommand {
     /// Unique identifier for the badge
     #[arg(short, long)]
@@ -47,6 +48,21 @@ pub async fn create_badge(
     let keys = parse_private_key(private_key, true).await?;
     let client = create_client(&keys, relays, difficulty_target).await?;
 
+    let event_id =
+        send_badge_definition(&client, &keys, difficulty_target, sub_command_args).await?;
+    println!("Published badge definition with id:");
+    println!("Hex: {}", event_id.to_hex());
+    println!("Bech32: {}", event_id.to_bech32()?);
+
+    Ok(())
+}
+
+pub async fn send_badge_definition(
+    client: &Client,
+    keys: &Keys,
+    difficulty_target: u8,
+    sub_command_args: &CreateBadgeSubCommand,
+) -> Result<EventId> {
     let image_size = match (
         sub_command_args.image_size_height,
         sub_command_args.image_size_width,
@@ -86,13 +102,10 @@ pub async fn create_badge(
         image_size,
         thumbnails,
     )
-    .to_pow_event(&keys, difficulty_target)?;
+    .to_pow_event(keys, difficulty_target)?;
 
     // Publish event
     let event_id = client.send_event(event).await?;
-    println!("Published badge definition with id:");
-    println!("Hex: {}", event_id.to_hex());
-    println!("Bech32: {}", event_id.to_bech32()?);
 
-    Ok(())
+    Ok(event_id)
 }
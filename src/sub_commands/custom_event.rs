@@ -1,11 +1,10 @@
-use std::borrow::Cow;
-
 use clap::Args;
 use nostr_sdk::prelude::*;
+use serde::Deserialize;
 
-use crate::utils::{create_client, parse_private_key};
+use crate::utils::{create_client, parse_custom_tag, parse_private_key, read_content};
 
-#[derive(Args)]
+#[derive(Args, Deserialize)]
 pub struct CustomEventCommand {
     /// Event kind
     #[arg(short, long)]
@@ -15,15 +14,26 @@ pub struct CustomEventCommand {
     #[arg(short, long)]
     content: Option<String>,
 
+    /// Read the content from a file, use - to read from stdin
+    #[arg(long, conflicts_with = "content")]
+    content_file: Option<String>,
+
+    /// Write the content in $EDITOR, starting with the given content if any
+    #[arg(long, default_value = "false")]
+    #[serde(default)]
+    edit: bool,
+
     /// Arbitrary tags. Specify first the tag key, then separate each string you want in the array with the character '|'.
     /// Example for adding an a-tag: "a|30001:b2d670de53b27691c0c3400225b65c35a26d06093bcc41f48ffc71e0907f9d4a:bookmark|wss://nostr.oxtr.dev"
     ///
     /// This will result in an array that looks like this: ["a", "30001:b2d670de53b27691c0c3400225b65c35a26d06093bcc41f48ffc71e0907f9d4a:bookmark", "wss://nostr.oxtr.dev"]
     #[arg(short, long, action = clap::ArgAction::Append)]
+    #[serde(default)]
     tags: Vec<String>,
 
     // Print keys as hex
     #[arg(long, default_value = "false")]
+    #[serde(default)]
     hex: bool,
 }
 
@@ -40,38 +50,46 @@ pub async fn create_custom_event(
     let keys = parse_private_key(private_key, true).await?;
     let client = create_client(&keys, relays, difficulty_target).await?;
 
+    let event_id = send_custom_event(&client, &keys, difficulty_target, sub_command_args).await?;
+
+    if !sub_command_args.hex {
+        println!("Published custom event with id: {}", event_id.to_bech32()?);
+    } else {
+        println!("Published custom event with id: {}", event_id.to_hex());
+    }
+
+    Ok(())
+}
+
+pub async fn send_custom_event(
+    client: &Client,
+    keys: &Keys,
+    difficulty_target: u8,
+    sub_command_args: &CustomEventCommand,
+) -> Result<EventId> {
     // Parse kind input
     let kind = Kind::Custom(sub_command_args.kind);
 
     // Set content
-    let content = sub_command_args
-        .content
-        .clone()
-        .unwrap_or_else(|| String::from(""));
+    let content = read_content(
+        sub_command_args.content.as_deref(),
+        sub_command_args.content_file.as_deref(),
+        sub_command_args.edit,
+    )?
+    .unwrap_or_default();
 
     // Set up tags
     let mut tags: Vec<Tag> = vec![];
 
-    for tag in sub_command_args.tags.clone().iter() {
-        let parts: Vec<String> = tag.split('|').map(String::from).collect();
-        let tag_kind = parts.first().unwrap().clone();
-        tags.push(Tag::custom(
-            TagKind::Custom(Cow::from(tag_kind)),
-            parts[1..].to_vec(),
-        ));
+    for tag in sub_command_args.tags.iter() {
+        tags.push(parse_custom_tag(tag));
     }
 
     // Initialize event builder
-    let event = EventBuilder::new(kind, content, tags).to_pow_event(&keys, difficulty_target)?;
+    let event = EventBuilder::new(kind, content, tags).to_pow_event(keys, difficulty_target)?;
 
     // Publish event
     let event_id = client.send_event(event).await?;
 
-    if !sub_command_args.hex {
-        println!("Published custom event with id: {}", event_id.to_bech32()?);
-    } else {
-        println!("Published custom event with id: {}", event_id.to_hex());
-    }
-
-    Ok(())
+    Ok(event_id)
 }
@@ -1,15 +1,17 @@
 use clap::Args;
 use nostr_sdk::prelude::*;
+use serde::Deserialize;
 
 use crate::utils::{create_client, parse_private_key};
 
-#[derive(Args)]
+#[derive(Args, Deserialize)]
 pub struct DeleteEventSubCommand {
     /// Event id to delete. Must be in hex format.
     #[arg(short, long)]
     event_id: String,
     /// Print keys as hex
     #[arg(long, default_value = "false")]
+    #[serde(default)]
     hex: bool,
 }
 
@@ -26,9 +28,7 @@ pub async fn delete(
     let keys = parse_private_key(private_key, true).await?;
     let client = create_client(&keys, relays, difficulty_target).await?;
 
-    let event_id_to_delete = EventId::from_hex(sub_command_args.event_id.clone())?;
-
-    let event_id = client.delete_event(event_id_to_delete).await?;
+    let event_id = send_deletion(&client, sub_command_args).await?;
     if !sub_command_args.hex {
         println!("Deleted event with id: {}", event_id.to_bech32()?);
     } else {
@@ -36,3 +36,13 @@ pub async fn delete(
     }
     Ok(())
 }
+
+pub async fn send_deletion(
+    client: &Client,
+    sub_command_args: &DeleteEventSubCommand,
+) -> Result<EventId> {
+    let event_id_to_delete = EventId::from_hex(sub_command_args.event_id.clone())?;
+
+    let event_id = client.delete_event(event_id_to_delete).await?;
+    Ok(event_id)
+}
@@ -1,48 +1,152 @@
-use std::{str::FromStr, time::Duration};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::time::Duration;
 
 use clap::Args;
+use nostr_sdk::nostr::nips::nip13;
 use nostr_sdk::prelude::*;
+use regex::Regex;
+use serde_json::Map;
+use tokio::sync::broadcast::error::RecvError;
 
-use crate::utils::create_client;
+use crate::timeline::Timeline;
+use crate::utils::{
+    create_client, parse_coordinate, parse_event_id_with_hints, parse_public_key_with_hints,
+    parse_tag_filter, parse_timestamp,
+};
 
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// JSON array of raw events, or JSON lines when following
+    Json,
+    /// One JSON event per line. With --all, each page is written as soon as it is fetched.
+    Jsonl,
+    /// Comma separated values with the columns selected by --columns. With --all, each page is
+    /// written as soon as it is fetched.
+    Csv,
+    /// Human readable timeline with author names and relative timestamps
+    Pretty,
+}
+
+// Columns available for the CSV format in addition to "tag:<name>"
+const CSV_COLUMNS: [&str; 9] = [
+    "id",
+    "pubkey",
+    "npub",
+    "created_at",
+    "kind",
+    "content",
+    "tags",
+    "sig",
+    "seen_on",
+];
+
+// Filter arguments shared by the commands that query relays
 #[derive(Args)]
-pub struct ListEventsSubCommand {
-    /// Ids
+pub struct FilterArgs {
+    /// Ids. Hex, note and nevent are supported.
     #[arg(short, long, action = clap::ArgAction::Append)]
     ids: Option<Vec<String>>,
-    /// Authors
+    /// Authors. Hex, npub, nprofile, naddr and NIP-05 identifiers are supported.
     #[arg(short, long, action = clap::ArgAction::Append)]
     authors: Option<Vec<String>>,
     /// Kinds
     #[arg(short, long, action = clap::ArgAction::Append)]
     kinds: Option<Vec<u64>>,
-    /// e tag
+    /// e tag. Hex, note and nevent are supported.
     #[arg(long, action = clap::ArgAction::Append)]
     etag: Option<Vec<String>>,
-    /// p tag
+    /// p tag. Hex, npub, nprofile, naddr and NIP-05 identifiers are supported.
     #[arg(long, action = clap::ArgAction::Append)]
     ptag: Option<Vec<String>>,
     /// d tag
     #[arg(long, action = clap::ArgAction::Append)]
     dtag: Option<Vec<String>>,
-    /// a tag
+    /// a tag. Either a kind:pubkey:d coordinate or a naddr.
     #[arg(long, action = clap::ArgAction::Append)]
     atag: Option<Vec<String>>,
-    /// Since
+    /// Single-letter tag filter using the syntax "<letter>=<value>", e.g. "t=bitcoin".
+    /// a-tag values must be kind:pubkey:d coordinates or naddr entities.
+    #[arg(long, action = clap::ArgAction::Append)]
+    tag: Vec<String>,
+    /// Full-text search query (NIP-50). Only supported by some relays.
+    #[arg(long)]
+    search: Option<String>,
+    /// NIP-50 search extension appended to the query, e.g. "language:en" or "domain:example.com"
+    #[arg(long, action = clap::ArgAction::Append, requires = "search")]
+    search_extension: Vec<String>,
+    /// Raw NIP-01 filter as JSON, e.g. '{"kinds":[1],"#t":["nostr"]}'. Can be repeated and is
+    /// combined with the other filter arguments. An argument replaces the field it sets in the
+    /// raw filter instead of being merged into it, e.g. --kinds 7 replaces "kinds" and
+    /// --tag t=rust replaces "#t".
+    #[arg(long, action = clap::ArgAction::Append)]
+    filter: Vec<String>,
+    /// Path to a JSON file with one filter object or an array of filter objects. The other filter
+    /// arguments replace the fields they set, like with --filter.
+    #[arg(long)]
+    filter_file: Option<String>,
+    /// Since. A unix timestamp, a duration ago such as 2h or 7d, an ISO-8601 date, today or yesterday.
     #[arg(short, long, action = clap::ArgAction::Append)]
-    since: Option<u64>,
-    /// Until
+    since: Option<String>,
+    /// Until. A unix timestamp, a duration ago such as 2h or 7d, an ISO-8601 date, today or yesterday.
     #[arg(short, long, action = clap::ArgAction::Append)]
-    until: Option<u64>,
+    until: Option<String>,
     /// Limit
     #[arg(short, long, action = clap::ArgAction::Append)]
     limit: Option<usize>,
+}
+
+#[derive(Args)]
+pub struct ListEventsSubCommand {
+    #[command(flatten)]
+    filter_args: FilterArgs,
     /// Output
     #[arg(short, long)]
     output: Option<String>,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+    format: OutputFormat,
+    /// CSV columns. Any of id, pubkey, npub, created_at, kind, content, tags, sig, seen_on,
+    /// or "tag:<name>" for the values of a tag, e.g. "tag:t".
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "id,pubkey,created_at,kind,content"
+    )]
+    columns: Vec<String>,
+    /// Append to the output file instead of overwriting it. Not supported for the json format.
+    #[arg(long, default_value = "false", requires = "output")]
+    append: bool,
     /// Timeout in seconds
     #[arg(long)]
     timeout: Option<u64>,
+    /// Fetch the complete history by paging backwards through each relay until no older events are returned
+    #[arg(long, default_value = "false", conflicts_with = "follow")]
+    all: bool,
+    /// Annotate each event with the relays that returned it and print a summary per relay
+    #[arg(long, default_value = "false", conflicts_with = "follow")]
+    seen_on: bool,
+    /// Drop events with an invalid id or signature
+    #[arg(long, default_value = "false")]
+    verify: bool,
+    /// Drop events that have expired (NIP-40)
+    #[arg(long, default_value = "false")]
+    drop_expired: bool,
+    /// Drop events with a proof of work difficulty below this value (NIP-13)
+    #[arg(long)]
+    min_pow: Option<u8>,
+    /// Drop events whose content doesn't match this regular expression
+    #[arg(long)]
+    grep: Option<String>,
+    /// Keep the subscription open and print new events as they arrive
+    #[arg(long, default_value = "false")]
+    follow: bool,
+    /// Stop following after this many events have been printed
+    #[arg(long, requires = "follow")]
+    max_events: Option<usize>,
+    /// Stop following after this many seconds
+    #[arg(long, requires = "follow")]
+    duration: Option<u64>,
 }
 
 pub async fn list_events(
@@ -53,37 +157,370 @@ pub async fn list_events(
         panic!("No relays specified, at least one relay is required!")
     }
 
+    if sub_command_args.append && sub_command_args.format == OutputFormat::Json {
+        return Err("Appending is not supported for the json format, use jsonl instead".into());
+    }
+    for column in sub_command_args.columns.iter() {
+        if !CSV_COLUMNS.contains(&column.as_str()) && !column.starts_with("tag:") {
+            return Err(format!("Unknown CSV column {column}").into());
+        }
+    }
+
+    let (filters, relay_hints) = build_filters(&sub_command_args.filter_args).await?;
+    let post_filter = PostFilter::new(sub_command_args)?;
+
     let client = create_client(&Keys::generate(), relays, 0).await?;
+    add_relay_hints(&client, relay_hints).await;
+
+    if sub_command_args.follow {
+        return follow_events(&client, filters, &post_filter, sub_command_args).await;
+    }
+
+    let timeout = sub_command_args.timeout.map(Duration::from_secs);
+
+    // Line based formats don't need the whole history at once. The relays an event was seen on
+    // are only known at the end, so --seen-on still collects everything first.
+    let streaming = matches!(
+        sub_command_args.format,
+        OutputFormat::Jsonl | OutputFormat::Csv
+    );
+    if sub_command_args.all && streaming && !sub_command_args.seen_on {
+        let incomplete =
+            stream_all_events(&client, filters, timeout, &post_filter, sub_command_args).await?;
+        if !incomplete.is_empty() {
+            return Err(incomplete_history_error(&incomplete));
+        }
+        return Ok(());
+    }
+
+    let mut incomplete: Vec<Url> = Vec::new();
+    let mut events: Vec<Event> = if sub_command_args.all {
+        let (events, incomplete_relays) = fetch_all_events(&client, filters, timeout).await;
+        incomplete = incomplete_relays;
+        events
+    } else {
+        client.get_events_of(filters, timeout).await?
+    };
+
+    let mut dropped: BTreeMap<&str, usize> = BTreeMap::new();
+    events.retain(|event| match post_filter.drop_reason(event) {
+        Some(reason) => {
+            *dropped.entry(reason).or_default() += 1;
+            false
+        }
+        None => true,
+    });
+    print_dropped_summary(&dropped);
+
+    let mut seen_on: HashMap<EventId, Vec<String>> = HashMap::new();
+    if sub_command_args.seen_on {
+        let database = client.database();
+        for event in events.iter() {
+            let mut relays: Vec<String> = database
+                .event_seen_on_relays(event.id)
+                .await?
+                .unwrap_or_default()
+                .iter()
+                .map(|url| url.to_string())
+                .collect();
+            relays.sort();
+            seen_on.insert(event.id, relays);
+        }
+    }
+
+    match sub_command_args.format {
+        OutputFormat::Pretty => {
+            let mut timeline = Timeline::default();
+            timeline
+                .resolve_profiles(&client, &events, timeout.or(Some(Duration::from_secs(10))))
+                .await?;
+            events.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+            let known_events: HashMap<EventId, &Event> =
+                events.iter().map(|event| (event.id, event)).collect();
+            let (mut writer, _) = open_output(sub_command_args)?;
+            for event in events.iter() {
+                writeln!(writer, "{}", timeline.render(event, &known_events))?;
+                if let Some(relays) = seen_on.get(&event.id) {
+                    writeln!(writer, "  seen on: {}", relays.join(", "))?;
+                }
+                writeln!(writer)?;
+            }
+        }
+        OutputFormat::Json => {
+            let values: Vec<Value> = events
+                .iter()
+                .map(|event| event_to_json(event, seen_on.get(&event.id)))
+                .collect::<Result<Vec<Value>>>()?;
+            let (mut writer, _) = open_output(sub_command_args)?;
+            serde_json::to_writer_pretty(&mut writer, &values)?;
+            writeln!(writer)?;
+        }
+        OutputFormat::Jsonl => {
+            let (mut writer, _) = open_output(sub_command_args)?;
+            for event in events.iter() {
+                writeln!(writer, "{}", event_to_json(event, seen_on.get(&event.id))?)?;
+            }
+        }
+        OutputFormat::Csv => {
+            let (writer, has_content) = open_output(sub_command_args)?;
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            if !has_content {
+                csv_writer.write_record(&sub_command_args.columns)?;
+            }
+            for event in events.iter() {
+                let relays = seen_on.get(&event.id).cloned().unwrap_or_default();
+                csv_writer.write_record(csv_row(&sub_command_args.columns, event, &relays)?)?;
+            }
+            csv_writer.flush()?;
+        }
+    }
+
+    if let Some(output) = &sub_command_args.output {
+        println!("Wrote {} event(s) to {}", events.len(), output);
+    }
+
+    if sub_command_args.seen_on {
+        print_seen_on_summary(&seen_on);
+    }
+
+    // What was fetched is written anyway, but the export must not look complete
+    if !incomplete.is_empty() {
+        return Err(incomplete_history_error(&incomplete));
+    }
+
+    Ok(())
+}
+
+// Writes the whole history page by page as it is fetched, so it never has to fit in memory.
+// Events are de-duplicated by id across relays, but not sorted. Returns the relays that failed
+// before the end of their history was reached.
+async fn stream_all_events(
+    client: &Client,
+    filters: Vec<Filter>,
+    timeout: Option<Duration>,
+    post_filter: &PostFilter,
+    sub_command_args: &ListEventsSubCommand,
+) -> Result<Vec<Url>> {
+    let (mut writer, has_content) = open_output(sub_command_args)?;
+    let columns = &sub_command_args.columns;
+    if sub_command_args.format == OutputFormat::Csv && !has_content {
+        writer.write_all(&csv_line(columns)?)?;
+    }
+
+    let mut pages = HistoryPages::new(client, filters, timeout).await;
+    let mut fetched: HashSet<EventId> = HashSet::new();
+    let mut dropped: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut written = 0;
+    while let Some((_, page)) = pages.next_page().await {
+        for event in page.into_iter() {
+            if !fetched.insert(event.id) {
+                continue;
+            }
+            if let Some(reason) = post_filter.drop_reason(&event) {
+                *dropped.entry(reason).or_default() += 1;
+                continue;
+            }
+            match sub_command_args.format {
+                OutputFormat::Csv => {
+                    writer.write_all(&csv_line(&csv_row(columns, &event, &[])?)?)?
+                }
+                _ => writeln!(writer, "{}", event_to_json(&event, None)?)?,
+            }
+            written += 1;
+        }
+        writer.flush()?;
+    }
+    eprintln!("Fetched {} unique event(s)", fetched.len());
+    print_dropped_summary(&dropped);
+
+    if let Some(output) = &sub_command_args.output {
+        println!("Wrote {written} event(s) to {output}");
+    }
+
+    Ok(pages.incomplete().to_vec())
+}
+
+// Opens the output file, or stdout if none was given. Also returns whether the file already has
+// content, in which case CSV headers are not written again.
+fn open_output(sub_command_args: &ListEventsSubCommand) -> Result<(Box<dyn Write>, bool)> {
+    match &sub_command_args.output {
+        Some(output) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(sub_command_args.append)
+                .truncate(!sub_command_args.append)
+                .open(output)?;
+            let has_content = file.metadata()?.len() > 0;
+            Ok((Box::new(std::io::BufWriter::new(file)), has_content))
+        }
+        None => Ok((Box::new(std::io::stdout()), false)),
+    }
+}
+
+fn event_to_json(event: &Event, seen_on: Option<&Vec<String>>) -> Result<Value> {
+    let mut value = serde_json::to_value(event)?;
+    if let Some(relays) = seen_on {
+        value["seen_on"] = serde_json::json!(relays);
+    }
+    Ok(value)
+}
+
+fn csv_row(columns: &[String], event: &Event, seen_on: &[String]) -> Result<Vec<String>> {
+    let mut row: Vec<String> = Vec::with_capacity(columns.len());
+    for column in columns.iter() {
+        let value = match column.as_str() {
+            "id" => event.id.to_hex(),
+            "pubkey" => event.pubkey.to_hex(),
+            "npub" => event.pubkey.to_bech32()?,
+            "created_at" => event.created_at.as_u64().to_string(),
+            "kind" => event.kind.as_u32().to_string(),
+            "content" => event.content.clone(),
+            "tags" => serde_json::to_string(&event.tags)?,
+            "sig" => event.sig.to_string(),
+            "seen_on" => seen_on.join(" "),
+            column => {
+                // Validated before fetching, everything else is "tag:<name>"
+                let name = column.trim_start_matches("tag:");
+                let values: Vec<&str> = event
+                    .tags
+                    .iter()
+                    .filter(|tag| tag.as_vec().first().map(|n| n.as_str()) == Some(name))
+                    .filter_map(|tag| tag.as_vec().get(1).map(|v| v.as_str()))
+                    .collect();
+                values.join(",")
+            }
+        };
+        row.push(value);
+    }
+    Ok(row)
+}
+
+// Encodes a single CSV record, used when rows are written one at a time
+fn csv_line(record: &[String]) -> Result<Vec<u8>> {
+    let mut csv_writer = csv::Writer::from_writer(Vec::new());
+    csv_writer.write_record(record)?;
+    Ok(csv_writer.into_inner()?)
+}
+
+// Client-side checks for events that relays shouldn't have returned
+struct PostFilter {
+    verify: bool,
+    drop_expired: bool,
+    min_pow: Option<u8>,
+    grep: Option<Regex>,
+}
+
+impl PostFilter {
+    fn new(sub_command_args: &ListEventsSubCommand) -> Result<Self> {
+        let grep = match &sub_command_args.grep {
+            Some(pattern) => Some(Regex::new(pattern)?),
+            None => None,
+        };
+        Ok(Self {
+            verify: sub_command_args.verify,
+            drop_expired: sub_command_args.drop_expired,
+            min_pow: sub_command_args.min_pow,
+            grep,
+        })
+    }
+
+    // Returns why the event should be dropped, if it should be
+    fn drop_reason(&self, event: &Event) -> Option<&'static str> {
+        if self.verify {
+            if event.verify_id().is_err() {
+                return Some("invalid id");
+            }
+            if event.verify_signature().is_err() {
+                return Some("invalid signature");
+            }
+        }
+        if self.drop_expired && event.is_expired() {
+            return Some("expired");
+        }
+        if let Some(min_pow) = self.min_pow {
+            if nip13::get_leading_zero_bits(event.id.as_bytes()) < min_pow {
+                return Some("insufficient proof of work");
+            }
+        }
+        if let Some(grep) = &self.grep {
+            if !grep.is_match(&event.content) {
+                return Some("content doesn't match");
+            }
+        }
+        None
+    }
+}
+
+fn print_dropped_summary(dropped: &BTreeMap<&str, usize>) {
+    if dropped.is_empty() {
+        return;
+    }
+    let total: usize = dropped.values().sum();
+    let reasons: Vec<String> = dropped
+        .iter()
+        .map(|(reason, count)| format!("{count} {reason}"))
+        .collect();
+    eprintln!("Dropped {total} event(s): {}", reasons.join(", "));
+}
+
+// Prints how many of the returned events each relay delivered
+fn print_seen_on_summary(seen_on: &HashMap<EventId, Vec<String>>) {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for relays in seen_on.values() {
+        for relay in relays.iter() {
+            *counts.entry(relay.as_str()).or_default() += 1;
+        }
+    }
+    let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    let width = counts
+        .iter()
+        .map(|(relay, _)| relay.len())
+        .max()
+        .unwrap_or(0)
+        .max("Total unique".len());
+    eprintln!("{:<width$}  Events", "Relay");
+    for (relay, count) in counts {
+        eprintln!("{relay:<width$}  {count}");
+    }
+    eprintln!("{:<width$}  {}", "Total unique", seen_on.len());
+}
+
+// Builds the filters from the command line arguments. Relay hints embedded in NIP-19 entities
+// are returned so they can be added to the query.
+pub async fn build_filters(filter_args: &FilterArgs) -> Result<(Vec<Filter>, Vec<String>)> {
     let mut filter = Filter::new();
 
+    let mut relay_hints: Vec<String> = Vec::new();
+
     // Handle event ids
-    if sub_command_args.ids.is_some() {
-        let ids: Vec<EventId> = sub_command_args
-            .ids
-            .clone()
-            .unwrap_or_default()
-            .iter()
-            .map(|id| EventId::from_str(id).unwrap())
-            .collect();
+    if filter_args.ids.is_some() {
+        let mut ids: Vec<EventId> = Vec::new();
+        for id in filter_args.ids.clone().unwrap_or_default().iter() {
+            let (event_id, hints) = parse_event_id_with_hints(id).await?;
+            ids.push(event_id);
+            relay_hints.extend(hints);
+        }
         filter = filter.ids(ids);
     }
 
     // Handle author public keys
-    if sub_command_args.authors.is_some() {
-        let authors: Vec<PublicKey> = sub_command_args
-            .authors
-            .clone()
-            .unwrap_or_default()
-            .iter()
-            .map(|author_pubkey| PublicKey::from_str(author_pubkey).unwrap())
-            .collect();
+    if filter_args.authors.is_some() {
+        let mut authors: Vec<PublicKey> = Vec::new();
+        for author in filter_args.authors.clone().unwrap_or_default().iter() {
+            let (public_key, hints) = parse_public_key_with_hints(author).await?;
+            authors.push(public_key);
+            relay_hints.extend(hints);
+        }
         filter = filter.authors(authors);
     }
 
     // Handle kind numbers
-    if sub_command_args.kinds.is_some() {
+    if filter_args.kinds.is_some() {
         // Convert kind number to Kind struct
-        let kinds: Vec<Kind> = sub_command_args
+        let kinds: Vec<Kind> = filter_args
             .kinds
             .clone()
             .unwrap_or_default()
@@ -95,65 +532,452 @@ pub async fn list_events(
     }
 
     // Handle e-tags
-    if sub_command_args.etag.is_some() {
-        // Convert event id string to EventId struct
-        let events: Vec<EventId> = sub_command_args
-            .etag
-            .clone()
-            .unwrap_or_default()
-            .into_iter()
-            .map(|e| {
-                if e.starts_with("note1") {
-                    EventId::from_bech32(e.as_str()).expect("Invalid event id")
-                } else {
-                    EventId::from_str(e.as_str()).expect("Invalid event id")
-                }
-            })
-            .collect();
+    if filter_args.etag.is_some() {
+        let mut events: Vec<EventId> = Vec::new();
+        for e in filter_args.etag.clone().unwrap_or_default().iter() {
+            let (event_id, hints) = parse_event_id_with_hints(e).await?;
+            events.push(event_id);
+            relay_hints.extend(hints);
+        }
         filter = filter.events(events);
     }
 
     // Handle p-tags
-    if sub_command_args.ptag.is_some() {
-        // Convert pubkey strings to XOnlyPublicKey struct
-        let pubkeys: Vec<PublicKey> = sub_command_args
-            .ptag
-            .clone()
-            .unwrap_or_default()
-            .into_iter()
-            .map(|p| PublicKey::from_str(p.as_str()).expect("Invalid public key"))
-            .collect();
+    if filter_args.ptag.is_some() {
+        let mut pubkeys: Vec<PublicKey> = Vec::new();
+        for p in filter_args.ptag.clone().unwrap_or_default().iter() {
+            let (public_key, hints) = parse_public_key_with_hints(p).await?;
+            pubkeys.push(public_key);
+            relay_hints.extend(hints);
+        }
         filter = filter.pubkeys(pubkeys);
     }
 
     // Handle d-tags
-    if sub_command_args.dtag.is_some() {
-        filter = filter.identifiers(sub_command_args.dtag.clone().unwrap_or_default());
+    if filter_args.dtag.is_some() {
+        filter = filter.identifiers(filter_args.dtag.clone().unwrap_or_default());
     }
 
-    if sub_command_args.since.is_some() {
-        filter = filter.since(sub_command_args.since.map(Timestamp::from).unwrap())
+    // Handle a-tags and generic single-letter tags
+    let mut tag_filters: HashMap<SingleLetterTag, Vec<String>> = HashMap::new();
+    let atag_filters = filter_args
+        .atag
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|a| format!("a={a}"));
+    for tag_filter in atag_filters.chain(filter_args.tag.iter().cloned()) {
+        if let Some(naddr) = tag_filter.strip_prefix("a=") {
+            relay_hints.extend(parse_coordinate(naddr)?.relays);
+        }
+        let (tag, value) = parse_tag_filter(&tag_filter)?;
+        tag_filters.entry(tag).or_default().push(value);
+    }
+    for (tag, values) in tag_filters {
+        filter = filter.custom_tag(tag, values);
     }
 
-    if sub_command_args.until.is_some() {
-        filter = filter.until(sub_command_args.until.map(Timestamp::from).unwrap())
+    // Handle full-text search (NIP-50)
+    if let Some(search) = &filter_args.search {
+        let mut query = vec![search.clone()];
+        query.extend(filter_args.search_extension.iter().cloned());
+        filter = filter.search(query.join(" "));
     }
 
-    if sub_command_args.limit.is_some() {
-        filter = filter.limit(sub_command_args.limit.unwrap())
+    if let Some(since) = &filter_args.since {
+        filter = filter.since(parse_timestamp(since)?)
     }
 
-    let timeout = sub_command_args.timeout.map(Duration::from_secs);
+    if let Some(until) = &filter_args.until {
+        filter = filter.until(parse_timestamp(until)?)
+    }
 
-    let events: Vec<Event> = client.get_events_of(vec![filter], timeout).await?;
+    if filter_args.limit.is_some() {
+        filter = filter.limit(filter_args.limit.unwrap())
+    }
 
-    if let Some(output) = &sub_command_args.output {
-        let file = std::fs::File::create(output)?;
-        serde_json::to_writer_pretty(file, &events)?;
-        println!("Wrote {} event(s) to {}", events.len(), output);
-    } else {
-        println!("{}", serde_json::to_string_pretty(&events)?)
+    let raw_filters = read_raw_filters(filter_args)?;
+    if raw_filters.is_empty() {
+        return Ok((vec![filter], relay_hints));
+    }
+
+    // The filter arguments are applied on top of every raw filter, replacing the fields they set
+    let Value::Object(arguments) = serde_json::to_value(&filter)? else {
+        return Err("Unable to serialize filter".into());
+    };
+    let mut filters: Vec<Filter> = Vec::new();
+    for mut raw_filter in raw_filters.into_iter() {
+        for (key, value) in arguments.iter() {
+            raw_filter.insert(key.clone(), value.clone());
+        }
+        filters.push(serde_json::from_value(Value::Object(raw_filter))?);
+    }
+
+    Ok((filters, relay_hints))
+}
+
+// Reads the filter objects given with --filter and --filter-file
+fn read_raw_filters(filter_args: &FilterArgs) -> Result<Vec<Map<String, Value>>> {
+    let mut inputs: Vec<Value> = Vec::new();
+    for filter in filter_args.filter.iter() {
+        inputs.push(serde_json::from_str(filter)?);
+    }
+    if let Some(filter_file) = &filter_args.filter_file {
+        let file = std::fs::File::open(filter_file)?;
+        inputs.push(serde_json::from_reader(file)?);
+    }
+
+    let mut raw_filters = Vec::new();
+    for input in inputs.into_iter() {
+        match input {
+            Value::Object(raw_filter) => raw_filters.push(raw_filter),
+            Value::Array(values) => {
+                for value in values.into_iter() {
+                    match value {
+                        Value::Object(raw_filter) => raw_filters.push(raw_filter),
+                        other => return Err(format!("Invalid filter: {other}").into()),
+                    }
+                }
+            }
+            other => return Err(format!("Invalid filter: {other}").into()),
+        }
+    }
+    Ok(raw_filters)
+}
+
+// Walks backwards through the history of each relay with a sliding `until` window, since relays
+// cap the number of events returned per request. Pages are handed out one at a time, so callers
+// don't have to hold the whole history in memory.
+pub struct HistoryPages<'a> {
+    client: &'a Client,
+    timeout: Option<Duration>,
+    // Relay and filter pairs that are left to walk through, the current one is last
+    queue: Vec<(Url, Filter)>,
+    until: Option<Timestamp>,
+    seen_on_relay: HashSet<EventId>,
+    incomplete: Vec<Url>,
+}
+
+impl<'a> HistoryPages<'a> {
+    pub async fn new(client: &'a Client, filters: Vec<Filter>, timeout: Option<Duration>) -> Self {
+        let urls: Vec<Url> = client.relays().await.into_keys().collect();
+        let mut queue: Vec<(Url, Filter)> = urls
+            .iter()
+            .flat_map(|url| {
+                filters
+                    .iter()
+                    .map(move |filter| (url.clone(), filter.clone()))
+            })
+            .collect();
+        queue.reverse();
+        let until = queue.last().and_then(|(_, filter)| filter.until);
+        Self {
+            client,
+            timeout,
+            queue,
+            until,
+            seen_on_relay: HashSet::new(),
+            incomplete: Vec::new(),
+        }
+    }
+
+    // Returns the events of the next page and the relay they came from, None once the history of
+    // every relay has been walked through
+    pub async fn next_page(&mut self) -> Option<(Url, Vec<Event>)> {
+        loop {
+            let (url, filter) = self.queue.last()?.clone();
+            let mut page_filter = filter;
+            if let Some(until) = self.until {
+                page_filter = page_filter.until(until);
+            }
+
+            match self
+                .client
+                .get_events_from(vec![url.clone()], vec![page_filter], self.timeout)
+                .await
+            {
+                Ok(page) => {
+                    // The relay is done once it only returns events we already got from it
+                    let new_events: Vec<Event> = page
+                        .into_iter()
+                        .filter(|event| self.seen_on_relay.insert(event.id))
+                        .collect();
+                    if let Some(oldest) = new_events.iter().map(|event| event.created_at).min() {
+                        // Keep the oldest second in the next window, other events from that
+                        // second may have been cut off by the relay limit
+                        self.until = Some(oldest);
+                        eprintln!(
+                            "{url}: {} event(s), reached {}",
+                            self.seen_on_relay.len(),
+                            oldest.to_human_datetime()
+                        );
+                        return Some((url, new_events));
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{url}: failed to fetch events: {e}");
+                    if !self.incomplete.contains(&url) {
+                        self.incomplete.push(url);
+                    }
+                }
+            }
+
+            // Move on to the next relay and filter
+            self.queue.pop();
+            self.until = self.queue.last().and_then(|(_, filter)| filter.until);
+            self.seen_on_relay.clear();
+        }
     }
 
+    // Relays that failed before the end of their history was reached
+    pub fn incomplete(&self) -> &[Url] {
+        &self.incomplete
+    }
+}
+
+// Fetches the whole history matching the filters from every relay, merged and deduplicated. Also
+// returns the relays that failed before the end of their history was reached.
+pub async fn fetch_all_events(
+    client: &Client,
+    filters: Vec<Filter>,
+    timeout: Option<Duration>,
+) -> (Vec<Event>, Vec<Url>) {
+    let mut events: HashMap<EventId, Event> = HashMap::new();
+    let mut pages = HistoryPages::new(client, filters, timeout).await;
+    while let Some((_, page)) = pages.next_page().await {
+        for event in page.into_iter() {
+            events.insert(event.id, event);
+        }
+    }
+
+    let mut events: Vec<Event> = events.into_values().collect();
+    events.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    eprintln!("Fetched {} unique event(s)", events.len());
+
+    (events, pages.incomplete().to_vec())
+}
+
+// Error for an export that is missing history because some relays failed
+pub fn incomplete_history_error(incomplete: &[Url]) -> Box<dyn std::error::Error> {
+    let urls: Vec<String> = incomplete.iter().map(|url| url.to_string()).collect();
+    format!(
+        "The history is incomplete, {} relay(s) failed before the end was reached: {}",
+        urls.len(),
+        urls.join(", ")
+    )
+    .into()
+}
+
+// Connects to the relays hinted by the filter inputs in addition to the configured ones
+pub async fn add_relay_hints(client: &Client, relay_hints: Vec<String>) {
+    let relay_hints: HashSet<String> = relay_hints.into_iter().collect();
+    if relay_hints.is_empty() {
+        return;
+    }
+    for relay in relay_hints {
+        // Hints are only read from, so nothing the client publishes later ends up there
+        let opts = RelayOptions::new().write(false);
+        if let Err(e) = client.add_relay_with_opts(relay.as_str(), opts).await {
+            eprintln!("Skipping relay hint {relay}: {e}");
+        }
+    }
+    client.connect().await;
+}
+
+// Keeps the subscription open and writes each new event as a JSON line until Ctrl-C
+// or one of the stop conditions is reached
+async fn follow_events(
+    client: &Client,
+    filters: Vec<Filter>,
+    post_filter: &PostFilter,
+    sub_command_args: &ListEventsSubCommand,
+) -> Result<()> {
+    let (mut writer, has_content) = open_output(sub_command_args)?;
+    if sub_command_args.format == OutputFormat::Csv && !has_content {
+        writer.write_all(&csv_line(&sub_command_args.columns)?)?;
+    }
+
+    let mut notifications = client.notifications();
+    let follow_subscription_id = client.subscribe(filters, None).await;
+    let mut timeline = Timeline::default();
+    let profile_timeout = Some(Duration::from_secs(sub_command_args.timeout.unwrap_or(5)));
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let duration = sub_command_args.duration.map(Duration::from_secs);
+    let stop = async move {
+        match duration {
+            Some(duration) => tokio::time::sleep(duration).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(stop);
+
+    // The same event is usually delivered by several relays, only print it once
+    let mut seen: HashSet<EventId> = HashSet::new();
+    let mut printed: usize = 0;
+    let mut dropped: BTreeMap<&str, usize> = BTreeMap::new();
+    loop {
+        tokio::select! {
+            _ = &mut ctrl_c => break,
+            _ = &mut stop => break,
+            notification = notifications.recv() => match notification {
+                Ok(RelayPoolNotification::Event { subscription_id, event, .. }) => {
+                    if subscription_id != follow_subscription_id || !seen.insert(event.id) {
+                        continue;
+                    }
+                    if let Some(reason) = post_filter.drop_reason(&event) {
+                        *dropped.entry(reason).or_default() += 1;
+                        continue;
+                    }
+                    match sub_command_args.format {
+                        OutputFormat::Json | OutputFormat::Jsonl => {
+                            writeln!(writer, "{}", event.as_json())?
+                        }
+                        OutputFormat::Csv => {
+                            let row = csv_row(&sub_command_args.columns, &event, &[])?;
+                            writer.write_all(&csv_line(&row)?)?
+                        }
+                        OutputFormat::Pretty => {
+                            let event = *event;
+                            let events = std::slice::from_ref(&event);
+                            timeline.resolve_profiles(client, events, profile_timeout).await?;
+                            writeln!(writer, "{}\n", timeline.render(&event, &HashMap::new()))?;
+                        }
+                    }
+                    writer.flush()?;
+                    printed += 1;
+                    if sub_command_args.max_events.is_some_and(|max| printed >= max) {
+                        break;
+                    }
+                }
+                Ok(RelayPoolNotification::Shutdown) | Err(RecvError::Closed) => break,
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+            },
+        }
+    }
+
+    client.disconnect().await?;
+    eprintln!("Received {printed} event(s)");
+    print_dropped_summary(&dropped);
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    const PUBKEY: &str = "b2d670de53b27691c0c3400225b65c35a26d06093bcc41f48ffc71e0907f9d4a";
+
+    #[derive(Parser)]
+    struct TestCommand {
+        #[command(flatten)]
+        filter_args: FilterArgs,
+    }
+
+    fn filter_args(args: &[&str]) -> FilterArgs {
+        let args = std::iter::once("list-events").chain(args.iter().copied());
+        TestCommand::try_parse_from(args).unwrap().filter_args
+    }
+
+    fn filter_json(filter: &Filter) -> Value {
+        serde_json::to_value(filter).unwrap()
+    }
+
+    #[test]
+    fn test_read_raw_filters() {
+        let raw_filters = read_raw_filters(&filter_args(&[
+            "--filter",
+            r#"{"kinds":[1]}"#,
+            "--filter",
+            r##"[{"#t":["nostr"]},{"limit":5}]"##,
+        ]))
+        .unwrap();
+        let raw_filters: Vec<Value> = raw_filters.into_iter().map(Value::Object).collect();
+        assert_eq!(
+            raw_filters,
+            vec![
+                serde_json::json!({"kinds": [1]}),
+                serde_json::json!({"#t": ["nostr"]}),
+                serde_json::json!({"limit": 5}),
+            ]
+        );
+
+        let path =
+            std::env::temp_dir().join(format!("nostr-tool-filters-{}.json", std::process::id()));
+        std::fs::write(&path, r#"[{"kinds":[0]},{"kinds":[3]}]"#).unwrap();
+        let raw_filters =
+            read_raw_filters(&filter_args(&["--filter-file", &path.to_string_lossy()]));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(raw_filters.unwrap().len(), 2);
+
+        assert!(read_raw_filters(&filter_args(&[])).unwrap().is_empty());
+        for invalid in ["5", "[1]", r#""kinds""#, "not json", "[{}, null]"] {
+            assert!(
+                read_raw_filters(&filter_args(&["--filter", invalid])).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_filters() {
+        // Without raw filters, the arguments make up a single filter
+        let (filters, _) = build_filters(&filter_args(&["--kinds", "1", "--authors", PUBKEY]))
+            .await
+            .unwrap();
+        assert_eq!(filters.len(), 1);
+        assert_eq!(
+            filter_json(&filters[0]),
+            serde_json::json!({"kinds": [1], "authors": [PUBKEY]})
+        );
+
+        // Every raw filter gets the arguments, which replace the fields they set
+        let (filters, _) = build_filters(&filter_args(&[
+            "--filter",
+            r##"[{"kinds":[1],"#t":["nostr"]},{"kinds":[30023]}]"##,
+            "--kinds",
+            "7",
+            "--tag",
+            "t=rust",
+            "--limit",
+            "10",
+        ]))
+        .await
+        .unwrap();
+        let filters: Vec<Value> = filters.iter().map(filter_json).collect();
+        assert_eq!(
+            filters,
+            vec![
+                serde_json::json!({"kinds": [7], "#t": ["rust"], "limit": 10}),
+                serde_json::json!({"kinds": [7], "#t": ["rust"], "limit": 10}),
+            ]
+        );
+
+        // Fields the arguments don't set are kept
+        let (filters, _) = build_filters(&filter_args(&[
+            "--filter",
+            r##"{"kinds":[1],"#t":["nostr"]}"##,
+            "--authors",
+            PUBKEY,
+        ]))
+        .await
+        .unwrap();
+        assert_eq!(
+            filter_json(&filters[0]),
+            serde_json::json!({"kinds": [1], "#t": ["nostr"], "authors": [PUBKEY]})
+        );
+
+        assert!(
+            build_filters(&filter_args(&["--filter", r#"{"kinds":"x"}"#]))
+                .await
+                .is_err()
+        );
+        assert!(build_filters(&filter_args(&["--filter", "[1]"]))
+            .await
+            .is_err());
+    }
+}
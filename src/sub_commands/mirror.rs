@@ -0,0 +1,134 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use clap::Args;
+use nostr_sdk::prelude::*;
+
+use crate::sub_commands::list_events::{
+    add_relay_hints, build_filters, incomplete_history_error, FilterArgs, HistoryPages,
+};
+use crate::utils::create_client;
+
+// Number of ids asked for at once when checking which events a destination already has
+const ID_CHUNK_SIZE: usize = 250;
+
+#[derive(Args)]
+pub struct MirrorSubCommand {
+    #[command(flatten)]
+    filter_args: FilterArgs,
+    /// Relays to read events from. Defaults to the relays given with --relays.
+    #[arg(long, action = clap::ArgAction::Append)]
+    from: Vec<String>,
+    /// Relays to publish the events to
+    #[arg(long, action = clap::ArgAction::Append, required = true)]
+    to: Vec<String>,
+    /// Timeout in seconds
+    #[arg(long, default_value_t = 30)]
+    timeout: u64,
+}
+
+pub async fn mirror(relays: Vec<String>, sub_command_args: &MirrorSubCommand) -> Result<()> {
+    let sources = if sub_command_args.from.is_empty() {
+        relays
+    } else {
+        sub_command_args.from.clone()
+    };
+    if sources.is_empty() {
+        panic!("No relays specified, at least one relay is required!")
+    }
+
+    let timeout = Some(Duration::from_secs(sub_command_args.timeout));
+    let (filters, relay_hints) = build_filters(&sub_command_args.filter_args).await?;
+
+    let source_client = create_client(&Keys::generate(), sources, 0).await?;
+    add_relay_hints(&source_client, relay_hints).await;
+    let destination_client =
+        create_client(&Keys::generate(), sub_command_args.to.clone(), 0).await?;
+    let destinations: Vec<Url> = destination_client.relays().await.into_keys().collect();
+
+    // Each page is published before the next one is fetched, so the whole history is never held
+    // in memory. Only the ids are kept, to skip events that several sources return.
+    let mut pages = HistoryPages::new(&source_client, filters, timeout).await;
+    let mut mirrored: HashSet<EventId> = HashSet::new();
+    let mut stats: HashMap<Url, MirrorStats> = HashMap::new();
+    let mut invalid = 0;
+    while let Some((_, page)) = pages.next_page().await {
+        let mut events: Vec<Event> = page
+            .into_iter()
+            .filter(|event| mirrored.insert(event.id))
+            .collect();
+        // Tampered events would be rejected by the destinations anyway
+        let total = events.len();
+        events.retain(|event| event.verify().is_ok());
+        invalid += total - events.len();
+        // Oldest first, so replaceable events within a page end up with their latest version
+        events.reverse();
+
+        for url in destinations.iter() {
+            let present = present_event_ids(&destination_client, url, &events, timeout).await;
+            let stats = stats.entry(url.clone()).or_default();
+            stats.present += present.len();
+            for event in events.iter().filter(|event| !present.contains(&event.id)) {
+                match destination_client
+                    .send_event_to(vec![url.clone()], event.clone())
+                    .await
+                {
+                    Ok(_) => stats.published += 1,
+                    Err(e) => {
+                        eprintln!("{url}: failed to publish {}: {e}", event.id);
+                        stats.failed += 1;
+                    }
+                }
+            }
+        }
+    }
+    let incomplete = pages.incomplete().to_vec();
+    source_client.disconnect().await?;
+
+    if invalid > 0 {
+        eprintln!("Skipped {invalid} event(s) with an invalid signature");
+    }
+    for url in destinations.iter() {
+        let stats = stats.remove(url).unwrap_or_default();
+        println!(
+            "{url}: {} published, {} already present, {} failed",
+            stats.published, stats.present, stats.failed
+        );
+    }
+
+    // What was fetched is mirrored anyway, but the mirror must not look complete
+    if !incomplete.is_empty() {
+        return Err(incomplete_history_error(&incomplete));
+    }
+
+    Ok(())
+}
+
+// Counts of what happened to the mirrored events on one destination
+#[derive(Default)]
+struct MirrorStats {
+    published: usize,
+    present: usize,
+    failed: usize,
+}
+
+// Asks the destination which of the events it already stores
+async fn present_event_ids(
+    client: &Client,
+    url: &Url,
+    events: &[Event],
+    timeout: Option<Duration>,
+) -> HashSet<EventId> {
+    let mut present: HashSet<EventId> = HashSet::new();
+    for chunk in events.chunks(ID_CHUNK_SIZE) {
+        let ids: Vec<EventId> = chunk.iter().map(|event| event.id).collect();
+        match client
+            .get_events_from(vec![url.clone()], vec![Filter::new().ids(ids)], timeout)
+            .await
+        {
+            Ok(found) => present.extend(found.into_iter().map(|event| event.id)),
+            Err(e) => eprintln!("{url}: unable to check for existing events: {e}"),
+        }
+    }
+    present
+}
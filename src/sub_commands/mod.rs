@@ -1,6 +1,9 @@
+pub mod archive;
 pub mod award_badge;
+pub mod batch;
 pub mod broadcast_events;
 pub mod convert_key;
+pub mod count;
 pub mod create_badge;
 pub mod create_public_channel;
 pub mod custom_event;
@@ -9,13 +12,18 @@ pub mod delete_profile;
 pub mod generate_keypair;
 pub mod hide_public_channel_message;
 pub mod list_events;
+pub mod mirror;
 pub mod mute_publickey;
 pub mod profile_badges;
 pub mod publish_contactlist_csv;
 pub mod react;
+pub mod repost;
+pub mod search_users;
 pub mod send_channel_message;
 pub mod set_channel_metadata;
 pub mod set_metadata;
+pub mod sync;
 pub mod text_note;
+pub mod thread;
 pub mod user_status;
 pub mod vanity;
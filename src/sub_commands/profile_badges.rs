@@ -3,16 +3,19 @@ use std::time::Duration;
 
 use clap::Args;
 use nostr_sdk::prelude::*;
+use serde::Deserialize;
 
 use crate::utils::{create_client, parse_private_key};
 
-#[derive(Args)]
+#[derive(Args, Deserialize)]
 pub struct ProfileBadgesSubCommand {
     /// Badge definition event id
     #[arg(short, long, action = clap::ArgAction::Append)]
+    #[serde(default)]
     badge_id: Vec<String>,
     /// Badge award event id
     #[arg(short, long, action = clap::ArgAction::Append)]
+    #[serde(default)]
     award_id: Vec<String>,
 }
 
@@ -29,6 +32,20 @@ pub async fn set_profile_badges(
     let keys = parse_private_key(private_key, true).await?;
     let client: Client = create_client(&keys, relays, difficulty_target).await?;
 
+    let event_id = send_profile_badges(&client, &keys, difficulty_target, sub_command_args).await?;
+    println!("Published profile badges event with id:");
+    println!("Hex: {}", event_id.to_hex());
+    println!("Bech32: {}", event_id.to_bech32()?);
+
+    Ok(())
+}
+
+pub async fn send_profile_badges(
+    client: &Client,
+    keys: &Keys,
+    difficulty_target: u8,
+    sub_command_args: &ProfileBadgesSubCommand,
+) -> Result<EventId> {
     let badge_definition_event_ids: Vec<EventId> = sub_command_args
         .badge_id
         .iter()
@@ -58,13 +75,10 @@ pub async fn set_profile_badges(
         badge_award_events,
         &keys.public_key(),
     )?
-    .to_pow_event(&keys, difficulty_target)?;
+    .to_pow_event(keys, difficulty_target)?;
 
     // Publish event
     let event_id = client.send_event(event).await?;
-    println!("Published profile badges event with id:");
-    println!("Hex: {}", event_id.to_hex());
-    println!("Bech32: {}", event_id.to_bech32()?);
 
-    Ok(())
+    Ok(event_id)
 }
@@ -1,12 +1,12 @@
-use std::process::exit;
 use std::time::Duration;
 
 use clap::Args;
 use nostr_sdk::prelude::*;
+use serde::Deserialize;
 
 use crate::utils::{create_client, parse_private_key};
 
-#[derive(Args)]
+#[derive(Args, Deserialize)]
 pub struct ReactionSubCommand {
     /// Event id to react to
     #[arg(short, long)]
@@ -19,6 +19,7 @@ pub struct ReactionSubCommand {
     reaction: String,
     // Print keys as hex
     #[arg(long, default_value = "false")]
+    #[serde(default)]
     hex: bool,
 }
 
@@ -35,9 +36,22 @@ pub async fn react_to_event(
     let keys = parse_private_key(private_key, true).await?;
     let client = create_client(&keys, relays, difficulty_target).await?;
 
+    let id = send_reaction(&client, sub_command_args).await?;
+    println!(
+        "Reacted to {} with {} in event {}",
+        EventId::from_hex(&sub_command_args.event_id)?.to_bech32()?,
+        sub_command_args.reaction,
+        id.to_bech32()?
+    );
+    Ok(())
+}
+
+pub async fn send_reaction(
+    client: &Client,
+    sub_command_args: &ReactionSubCommand,
+) -> Result<EventId> {
     if sub_command_args.reaction.trim().is_empty() {
-        eprintln!("Reaction does not contain any content");
-        exit(0)
+        return Err("Reaction does not contain any content".into());
     }
 
     let event_id = EventId::from_hex(&sub_command_args.event_id)?;
@@ -53,21 +67,12 @@ pub async fn react_to_event(
         )
         .await?;
 
-    if events.is_empty() {
-        eprintln!("Unable to find note with the provided event id");
-        exit(0);
-    }
-
-    let event_to_react_to = events.first().unwrap();
+    let event_to_react_to = events
+        .first()
+        .ok_or("Unable to find note with the provided event id")?;
 
     let id = client
         .reaction(event_to_react_to, sub_command_args.reaction.clone())
         .await?;
-    println!(
-        "Reacted to {} with {} in event {}",
-        event_id.to_bech32()?,
-        sub_command_args.reaction,
-        id.to_bech32()?
-    );
-    Ok(())
+    Ok(id)
 }
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use clap::Args;
+use nostr_sdk::prelude::*;
+use serde::Deserialize;
+
+use crate::sub_commands::list_events::add_relay_hints;
+use crate::utils::{
+    create_client, event_relay_hint, fetch_event, parse_event_id_with_hints, parse_private_key,
+};
+
+// How long to wait for relays when looking up the event to repost
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Args, Deserialize)]
+pub struct RepostSubCommand {
+    /// Event to repost. Hex, note and nevent are supported.
+    event: String,
+    // Print keys as hex
+    #[arg(long, default_value = "false")]
+    #[serde(default)]
+    hex: bool,
+}
+
+pub async fn repost(
+    private_key: Option<String>,
+    relays: Vec<String>,
+    difficulty_target: u8,
+    sub_command_args: &RepostSubCommand,
+) -> Result<()> {
+    if relays.is_empty() {
+        panic!("No relays specified, at least one relay is required!")
+    }
+
+    let keys = parse_private_key(private_key, true).await?;
+    let client = create_client(&keys, relays, difficulty_target).await?;
+
+    let event_id = send_repost(&client, sub_command_args).await?;
+    if !sub_command_args.hex {
+        println!("Published repost with id: {}", event_id.to_bech32()?);
+    } else {
+        println!("Published repost with id: {}", event_id.to_hex());
+    }
+
+    Ok(())
+}
+
+pub async fn send_repost(client: &Client, sub_command_args: &RepostSubCommand) -> Result<EventId> {
+    let (event_id, relay_hints) = parse_event_id_with_hints(&sub_command_args.event).await?;
+    add_relay_hints(client, relay_hints.clone()).await;
+
+    let original = fetch_event(client, event_id, Some(FETCH_TIMEOUT))
+        .await?
+        .ok_or("Unable to find the event to repost")?;
+    let relay = event_relay_hint(client, event_id, &relay_hints).await?;
+    let relay_url = Some(relay)
+        .filter(|relay| !relay.is_empty())
+        .map(UncheckedUrl::from);
+
+    // Text notes become a kind 6 repost, anything else a kind 16 generic repost (NIP-18).
+    // The original event is embedded as the content.
+    let mut builder = EventBuilder::repost(&original, relay_url.clone());
+    // Replaceable and addressable events are also referenced by their coordinate
+    if original.is_replaceable() || original.is_parameterized_replaceable() {
+        let coordinate = Coordinate::new(original.kind, original.pubkey)
+            .identifier(original.identifier().unwrap_or_default());
+        builder = builder.add_tags([Tag::from_standardized_without_cell(
+            TagStandard::Coordinate {
+                coordinate,
+                relay_url,
+            },
+        )]);
+    }
+    let event_id = client.send_event_builder(builder).await?;
+
+    Ok(event_id)
+}
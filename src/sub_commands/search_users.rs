@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use clap::Args;
+use nostr_sdk::prelude::*;
+
+use crate::utils::create_client;
+
+#[derive(Args)]
+pub struct SearchUsersSubCommand {
+    /// Search query (NIP-50), e.g. a name. Extensions like "language:en" can be part of the query.
+    #[arg(short, long)]
+    query: String,
+    /// Maximum number of profiles to return
+    #[arg(short, long, default_value_t = 20)]
+    limit: usize,
+    /// Timeout in seconds
+    #[arg(long)]
+    timeout: Option<u64>,
+}
+
+pub async fn search_users(
+    relays: Vec<String>,
+    sub_command_args: &SearchUsersSubCommand,
+) -> Result<()> {
+    if relays.is_empty() {
+        panic!("No relays specified, at least one relay is required!")
+    }
+
+    let client = create_client(&Keys::generate(), relays, 0).await?;
+
+    let filter = Filter::new()
+        .kind(Kind::Metadata)
+        .search(sub_command_args.query.clone())
+        .limit(sub_command_args.limit);
+    let timeout = Some(Duration::from_secs(sub_command_args.timeout.unwrap_or(10)));
+    let events: Vec<Event> = client.get_events_of(vec![filter], timeout).await?;
+
+    // Only keep the newest metadata event of each profile
+    let mut profiles: HashMap<PublicKey, &Event> = HashMap::new();
+    for event in events.iter() {
+        match profiles.get(&event.pubkey) {
+            Some(existing) if existing.created_at >= event.created_at => {}
+            _ => {
+                profiles.insert(event.pubkey, event);
+            }
+        }
+    }
+
+    if profiles.is_empty() {
+        println!("No profiles found. Make sure the relays support NIP-50 search.");
+        return Ok(());
+    }
+
+    let mut rows: Vec<(String, String, String)> = Vec::new();
+    for (public_key, event) in profiles.into_iter() {
+        let metadata = Metadata::from_json(&event.content).unwrap_or_default();
+        let name = metadata
+            .display_name
+            .filter(|name| !name.trim().is_empty())
+            .or(metadata.name)
+            .unwrap_or_default();
+        rows.push((
+            name,
+            public_key.to_bech32()?,
+            metadata.nip05.unwrap_or_default(),
+        ));
+    }
+    rows.sort();
+
+    let name_width = rows
+        .iter()
+        .map(|(name, _, _)| name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Name".len());
+    let npub_width = rows[0].1.len();
+    println!("{:<name_width$}  {:<npub_width$}  NIP-05", "Name", "Npub");
+    for (name, npub, nip05) in rows.iter() {
+        println!("{name:<name_width$}  {npub:<npub_width$}  {nip05}");
+    }
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use crate::utils::{create_client, parse_private_key};
+use crate::utils::{create_client, parse_private_key, read_content};
 use clap::Args;
 use nostr_sdk::prelude::*;
 
@@ -8,8 +8,14 @@ pub struct SendChannelMessageSubCommand {
     #[arg(short, long)]
     channel_id: String,
     /// Message content
-    #[arg(short, long)]
-    message: String,
+    #[arg(short, long, required_unless_present_any = ["message_file", "edit"])]
+    message: Option<String>,
+    /// Read the message from a file, use - to read from stdin
+    #[arg(long, conflicts_with = "message")]
+    message_file: Option<String>,
+    /// Write the message in $EDITOR, starting with the given message if any
+    #[arg(long, default_value = "false")]
+    edit: bool,
     // Print keys as hex
     #[arg(long, default_value = "false")]
     hex: bool,
@@ -30,13 +36,15 @@ pub async fn send_channel_message(
     let client = create_client(&keys, relays.clone(), difficulty_target).await?;
 
     let ch_id: EventId = EventId::from_hex(sub_command_args.channel_id.clone()).unwrap();
+    let message = read_content(
+        sub_command_args.message.as_deref(),
+        sub_command_args.message_file.as_deref(),
+        sub_command_args.edit,
+    )?
+    .ok_or("No message given, use --message, --message-file or --edit")?;
 
     let event_id = client
-        .send_channel_msg(
-            ch_id,
-            Url::parse(relays[0].as_str())?,
-            sub_command_args.message.clone(),
-        )
+        .send_channel_msg(ch_id, Url::parse(relays[0].as_str())?, message)
         .await?;
     println!(
         "Public channel message sent with id: {}",
@@ -1,10 +1,11 @@
 use clap::Args;
 use nostr_sdk::nostr::nips::nip05;
 use nostr_sdk::prelude::*;
+use serde::Deserialize;
 
 use crate::utils::{create_client, parse_private_key};
 
-#[derive(Args)]
+#[derive(Args, Deserialize)]
 pub struct SetMetadataSubCommand {
     /// Set profile name
     #[arg(short, long)]
@@ -26,12 +27,15 @@ pub struct SetMetadataSubCommand {
     lud16: Option<String>,
     /// External identities. Use this syntax: "platform:identity:proof"
     #[arg(short, long)]
+    #[serde(default)]
     identities: Vec<String>,
     /// Arbitrary fields not in the protocol. Use this syntax: "key:value"
     #[arg(short, long)]
+    #[serde(default)]
     extra_field: Vec<String>,
     /// Print keys as hex
     #[arg(long, default_value = "false")]
+    #[serde(default)]
     hex: bool,
 }
 
@@ -48,6 +52,18 @@ pub async fn set_metadata(
     let keys = parse_private_key(private_key, true).await?;
     let client = create_client(&keys, relays, difficulty_target).await?;
 
+    let event_id = send_metadata(&client, &keys, difficulty_target, sub_command_args).await?;
+    println!("New metadata event: {}", event_id.to_bech32()?);
+
+    Ok(())
+}
+
+pub async fn send_metadata(
+    client: &Client,
+    keys: &Keys,
+    difficulty_target: u8,
+    sub_command_args: &SetMetadataSubCommand,
+) -> Result<EventId> {
     let mut metadata = Metadata::new();
 
     // Name
@@ -107,10 +123,9 @@ pub async fn set_metadata(
 
     let event = EventBuilder::metadata(&metadata)
         .add_tags(identity_tags)
-        .to_pow_event(&keys, difficulty_target)
-        .unwrap();
+        .to_pow_event(keys, difficulty_target)?;
+
     let event_id = client.send_event(event).await?;
-    println!("New metadata event: {}", event_id.to_bech32()?);
 
-    Ok(())
+    Ok(event_id)
 }
@@ -0,0 +1,267 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::time::Duration;
+
+use clap::Args;
+use nostr_sdk::nostr::negentropy::{Bytes, Negentropy};
+use nostr_sdk::prelude::*;
+
+use crate::sub_commands::list_events::{build_filters, FilterArgs};
+use crate::utils::{create_client, read_events_file, EventsFileFormat};
+
+// Number of ids asked for at once when downloading the events the local file is missing
+const ID_CHUNK_SIZE: usize = 250;
+
+#[derive(Args)]
+pub struct SyncSubCommand {
+    /// Local event file (JSON array or JSON lines) to reconcile against the relays
+    #[arg(short, long)]
+    file_path: String,
+    #[command(flatten)]
+    filter_args: FilterArgs,
+    /// Download events missing from the local file and add them to it
+    #[arg(long, default_value = "false")]
+    download: bool,
+    /// Upload events missing from the relays
+    #[arg(long, default_value = "false")]
+    upload: bool,
+    /// Timeout in seconds
+    #[arg(long, default_value_t = 30)]
+    timeout: u64,
+}
+
+pub async fn sync(relays: Vec<String>, sub_command_args: &SyncSubCommand) -> Result<()> {
+    if relays.is_empty() {
+        panic!("No relays specified, at least one relay is required!")
+    }
+
+    // Relay hints in the filter arguments are ignored, local events must only be uploaded to the
+    // relays that were asked for
+    let (filters, _) = build_filters(&sub_command_args.filter_args).await?;
+
+    let (local_events, file_format) = read_events_file(&sub_command_args.file_path)?;
+    let local_events: Vec<Event> = local_events
+        .into_iter()
+        .filter(|event| filters.iter().any(|filter| filter.match_event(event)))
+        .collect();
+    let local_ids: HashSet<EventId> = local_events.iter().map(|event| event.id).collect();
+    println!("{} local event(s) match the filter", local_ids.len());
+
+    let mut urls: Vec<String> = relays;
+    urls.sort();
+    urls.dedup();
+
+    let timeout = Duration::from_secs(sub_command_args.timeout);
+
+    let mut downloaded: Vec<Event> = Vec::new();
+    let mut known_ids: HashSet<EventId> = local_ids.clone();
+    let mut unreconciled: Vec<String> = Vec::new();
+    for url in urls.into_iter() {
+        let client = match create_client(&Keys::generate(), vec![url.clone()], 0).await {
+            Ok(client) => client,
+            Err(e) => {
+                eprintln!("{url}: unable to connect: {e}");
+                unreconciled.push(url);
+                continue;
+            }
+        };
+        let relay = client.relay(&url).await?;
+
+        // Only the ids are exchanged, each side ends up knowing which events the other lacks
+        let mut missing_remotely: HashSet<EventId> = HashSet::new();
+        let mut missing_locally: HashSet<EventId> = HashSet::new();
+        let mut failed = false;
+        for filter in filters.iter() {
+            let items: Vec<(EventId, Timestamp)> = local_events
+                .iter()
+                .filter(|event| filter.match_event(event))
+                .map(|event| (event.id, event.created_at))
+                .collect();
+            match reconcile_ids(&relay, filter.clone(), items, timeout).await {
+                Ok((have, need)) => {
+                    missing_remotely.extend(have);
+                    missing_locally.extend(need);
+                }
+                Err(e) => {
+                    eprintln!("{url}: negentropy reconciliation failed: {e}");
+                    failed = true;
+                    break;
+                }
+            }
+        }
+        if failed {
+            client.disconnect().await?;
+            unreconciled.push(url);
+            continue;
+        }
+        println!(
+            "{url}: {} event(s) missing on the relay, {} event(s) missing locally",
+            missing_remotely.len(),
+            missing_locally.len()
+        );
+
+        if sub_command_args.upload && !missing_remotely.is_empty() {
+            let mut uploaded = 0;
+            for event in local_events
+                .iter()
+                .filter(|event| missing_remotely.contains(&event.id))
+            {
+                match client.send_event(event.clone()).await {
+                    Ok(_) => uploaded += 1,
+                    Err(e) => eprintln!("{url}: failed to upload {}: {e}", event.id),
+                }
+            }
+            println!("{url}: uploaded {uploaded} event(s)");
+        }
+
+        if sub_command_args.download {
+            let ids: Vec<EventId> = missing_locally
+                .into_iter()
+                .filter(|event_id| !known_ids.contains(event_id))
+                .collect();
+            for chunk in ids.chunks(ID_CHUNK_SIZE) {
+                let filter = Filter::new().ids(chunk.to_vec());
+                let events = match client
+                    .get_events_from(vec![url.clone()], vec![filter], Some(timeout))
+                    .await
+                {
+                    Ok(events) => events,
+                    Err(e) => {
+                        eprintln!("{url}: failed to download events: {e}");
+                        break;
+                    }
+                };
+                for event in events.into_iter() {
+                    if chunk.contains(&event.id)
+                        && event.verify().is_ok()
+                        && known_ids.insert(event.id)
+                    {
+                        downloaded.push(event);
+                    }
+                }
+            }
+        }
+
+        client.disconnect().await?;
+    }
+
+    if !downloaded.is_empty() {
+        write_downloaded_events(&sub_command_args.file_path, file_format, &downloaded)?;
+        println!(
+            "Added {} event(s) to {}",
+            downloaded.len(),
+            sub_command_args.file_path
+        );
+    }
+
+    if !unreconciled.is_empty() {
+        return Err(format!(
+            "Unable to reconcile with {} relay(s): {}",
+            unreconciled.len(),
+            unreconciled.join(", ")
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+// Runs a NIP-77 negentropy reconciliation of the local items against the events the relay has
+// for the filter. Returns the ids only the local side has and the ids only the relay has.
+// nostr-sdk's own reconciliation doesn't hand these out, it uploads and downloads by itself.
+async fn reconcile_ids(
+    relay: &Relay,
+    filter: Filter,
+    items: Vec<(EventId, Timestamp)>,
+    timeout: Duration,
+) -> Result<(Vec<EventId>, Vec<EventId>)> {
+    let mut negentropy = Negentropy::new(32, Some(20_000))?;
+    for (event_id, created_at) in items.into_iter() {
+        negentropy.add_item(created_at.as_u64(), Bytes::from_slice(event_id.as_bytes()))?;
+    }
+    negentropy.seal()?;
+
+    let subscription_id = SubscriptionId::generate();
+    let send_opts = RelaySendOptions::new().skip_send_confirmation(true);
+    let mut notifications = relay.notifications();
+    relay
+        .send_msg(
+            ClientMessage::neg_open(&mut negentropy, &subscription_id, filter)?,
+            send_opts,
+        )
+        .await?;
+
+    let mut have_ids: Vec<Bytes> = Vec::new();
+    let mut need_ids: Vec<Bytes> = Vec::new();
+    loop {
+        let notification = tokio::time::timeout(timeout, notifications.recv())
+            .await
+            .map_err(|_| "timeout, the relay may not support negentropy (NIP-77)")??;
+        let RelayNotification::Message { message } = notification else {
+            continue;
+        };
+        match message {
+            RelayMessage::NegMsg {
+                subscription_id: id,
+                message,
+            } if id == subscription_id => {
+                let query = Bytes::from_hex(message)?;
+                match negentropy.reconcile_with_ids(&query, &mut have_ids, &mut need_ids)? {
+                    Some(next) => {
+                        let message = ClientMessage::NegMsg {
+                            subscription_id: subscription_id.clone(),
+                            message: next.to_hex(),
+                        };
+                        relay.send_msg(message, send_opts).await?;
+                    }
+                    None => break,
+                }
+            }
+            RelayMessage::NegErr {
+                subscription_id: id,
+                code,
+            } if id == subscription_id => return Err(code.to_string().into()),
+            RelayMessage::Notice { message } if message.contains("NEG-") => {
+                return Err(format!("negentropy is not supported: {message}").into())
+            }
+            _ => {}
+        }
+    }
+    relay
+        .send_msg(ClientMessage::NegClose { subscription_id }, send_opts)
+        .await?;
+
+    let to_event_ids = |ids: Vec<Bytes>| -> Result<Vec<EventId>> {
+        ids.iter()
+            .map(|id| Ok(EventId::from_slice(id.as_bytes())?))
+            .collect()
+    };
+    Ok((to_event_ids(have_ids)?, to_event_ids(need_ids)?))
+}
+
+// Adds the downloaded events to the local file, keeping its format
+fn write_downloaded_events(
+    file_path: &str,
+    file_format: EventsFileFormat,
+    downloaded: &[Event],
+) -> Result<()> {
+    match file_format {
+        EventsFileFormat::Json => {
+            // A JSON array can't be appended to, so the whole file is rewritten
+            let (mut events, _) = read_events_file(file_path)?;
+            events.extend(downloaded.iter().cloned());
+            let file = std::fs::File::create(file_path)?;
+            serde_json::to_writer_pretty(file, &events)?;
+        }
+        EventsFileFormat::Jsonl => {
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file_path)?;
+            for event in downloaded.iter() {
+                writeln!(file, "{}", event.as_json())?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,29 +1,77 @@
-use std::ops::Add;
 use std::str::FromStr;
 use std::time::Duration;
 
 use clap::Args;
 use nostr_sdk::prelude::*;
+use regex::Regex;
+use serde::Deserialize;
 
-use crate::utils::{create_client, parse_private_key};
+use crate::sub_commands::list_events::add_relay_hints;
+use crate::utils::{
+    create_client, create_client_without_signer, deserialize_expiration, event_relay_hint,
+    fetch_event, fetch_relay_list, find_nostr_uris, nip10_references, parse_custom_tag,
+    parse_event_id_with_hints, parse_expiration, parse_private_key, read_content,
+};
 
-#[derive(Args)]
+// How long to wait for relays when looking up the note that is replied to or quoted
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Args, Deserialize)]
 pub struct TextNoteSubCommand {
     /// Text note content
-    #[arg(short, long)]
-    content: String,
+    #[arg(short, long, required_unless_present_any = ["content_file", "edit"])]
+    content: Option<String>,
+    /// Read the content from a file, use - to read from stdin
+    #[arg(long, conflicts_with = "content")]
+    content_file: Option<String>,
+    /// Write the content in $EDITOR, starting with the given content if any
+    #[arg(long, default_value = "false")]
+    #[serde(default)]
+    edit: bool,
     /// Subject tag (NIP-14)
     #[arg(short, long)]
     subject: Option<String>,
     /// Pubkey references. Both hex and bech32 encoded keys are supported.
     #[arg(long, action = clap::ArgAction::Append)]
+    #[serde(default)]
     ptag: Vec<String>,
     /// Event references
     #[arg(long, action = clap::ArgAction::Append)]
+    #[serde(default)]
     etag: Vec<String>,
-    /// Seconds till expiration (NIP-40)
+    /// Note to reply to (NIP-10). Hex, note and nevent are supported.
+    #[arg(long)]
+    reply_to: Option<String>,
+    /// Also publish the reply to the read relays of the author that is replied to (NIP-65)
+    #[arg(long, default_value = "false", requires = "reply_to")]
+    #[serde(default)]
+    to_author_relays: bool,
+    /// Event to quote (NIP-18), a nostr: reference to it is appended to the content. Hex, note and nevent are supported.
     #[arg(long)]
-    expiration: Option<u64>,
+    quote: Option<String>,
+    /// Expiration (NIP-40). Seconds from now, a duration such as 2h or 7d, or an ISO-8601 date.
+    #[arg(long)]
+    #[serde(default, deserialize_with = "deserialize_expiration")]
+    expiration: Option<String>,
+    /// Content warning (NIP-36), optionally with a reason
+    #[arg(long, num_args = 0..=1, default_missing_value = "")]
+    content_warning: Option<String>,
+    /// Language of the content as an ISO-639-1 code, e.g. en (NIP-32)
+    #[arg(long)]
+    language: Option<String>,
+    /// Self-label (NIP-32) as <namespace>=<value>, e.g. "com.example.ontology=meme". A value without a namespace is labeled as user generated content.
+    #[arg(long, action = clap::ArgAction::Append)]
+    #[serde(default)]
+    label: Vec<String>,
+    /// Arbitrary tags, in the same format as custom-event: the tag key, then each value separated by '|'.
+    /// Example: "r|https://example.com"
+    #[arg(long, action = clap::ArgAction::Append)]
+    #[serde(default)]
+    tag: Vec<String>,
+    /// Don't turn @npub mentions into nostr: URIs, and don't add tags for mentions and hashtags
+    #[arg(long, default_value = "false")]
+    #[serde(default)]
+    no_auto_tags: bool,
 }
 
 pub async fn broadcast_textnote(
@@ -39,14 +87,45 @@ pub async fn broadcast_textnote(
     let keys = parse_private_key(private_key, true).await?;
     let client = create_client(&keys, relays, difficulty_target).await?;
 
+    let event_id = send_textnote(&client, sub_command_args).await?;
+    println!("Published text note with id:");
+    println!("Hex: {}", event_id.to_hex());
+    println!("Bech32: {}", event_id.to_bech32()?);
+
+    Ok(())
+}
+
+pub async fn send_textnote(
+    client: &Client,
+    sub_command_args: &TextNoteSubCommand,
+) -> Result<EventId> {
     // Set up tags
     let mut tags: Vec<Tag> = vec![];
+    let mut author_relays: Vec<String> = Vec::new();
 
     // Subject tag (NIP-14)
     if let Some(subject) = &sub_command_args.subject {
         let subject_tag = Tag::custom(TagKind::Subject, vec![subject]);
         tags.push(subject_tag);
     }
+    // Content warning (NIP-36)
+    if let Some(reason) = &sub_command_args.content_warning {
+        let reason = Some(reason.clone()).filter(|reason| !reason.is_empty());
+        tags.push(Tag::from_standardized_without_cell(
+            TagStandard::ContentWarning { reason },
+        ));
+    }
+    // Self-labels (NIP-32)
+    for tag in self_label_tags(
+        sub_command_args.language.as_deref(),
+        &sub_command_args.label,
+    )? {
+        push_unique(&mut tags, tag);
+    }
+    // Arbitrary tags
+    for tag in sub_command_args.tag.iter() {
+        push_unique(&mut tags, parse_custom_tag(tag));
+    }
 
     // Add p-tags
     for ptag in sub_command_args.ptag.iter() {
@@ -59,19 +138,356 @@ pub async fn broadcast_textnote(
         let event_id = EventId::from_hex(etag)?;
         tags.push(Tag::event(event_id));
     }
+    // Reply tags (NIP-10)
+    if let Some(reply_to) = &sub_command_args.reply_to {
+        let (reply_tags, relays) =
+            reply_tags(client, reply_to, sub_command_args.to_author_relays).await?;
+        tags.extend(reply_tags);
+        author_relays = relays;
+    }
     // Set expiration tag
-    if let Some(expiration) = sub_command_args.expiration {
-        let timestamp = Timestamp::now().add(Duration::from_secs(expiration));
-        tags.push(Tag::expiration(timestamp));
+    if let Some(expiration) = &sub_command_args.expiration {
+        tags.push(Tag::expiration(parse_expiration(expiration)?));
+    }
+
+    let mut content = read_content(
+        sub_command_args.content.as_deref(),
+        sub_command_args.content_file.as_deref(),
+        sub_command_args.edit,
+    )?
+    .ok_or("No content given, use --content, --content-file or --edit")?;
+
+    // Quote repost (NIP-18)
+    if let Some(quote) = &sub_command_args.quote {
+        let (reference, quote_tags) = quote_reference(client, quote).await?;
+        content = format!("{content}\n\nnostr:{reference}");
+        tags.extend(quote_tags);
+    }
+    // Mentions (NIP-27) and hashtags
+    if !sub_command_args.no_auto_tags {
+        content = rewrite_mentions(&content);
+        for tag in content_tags(&content) {
+            push_unique(&mut tags, tag);
+        }
     }
 
     // Publish event
-    let event_id = client
-        .publish_text_note(sub_command_args.content.clone(), tags)
+    let event = client
+        .sign_event_builder(EventBuilder::text_note(content, tags))
         .await?;
-    println!("Published text note with id:");
-    println!("Hex: {}", event_id.to_hex());
-    println!("Bech32: {}", event_id.to_bech32()?);
+    let event_id = client.send_event(event.clone()).await?;
+    if !author_relays.is_empty() {
+        send_to_author_relays(event, author_relays).await?;
+    }
 
+    Ok(event_id)
+}
+
+// Sends the reply to the read relays of the author that is replied to. A separate client is
+// used, so later operations of a batch aren't published there as well.
+async fn send_to_author_relays(event: Event, author_relays: Vec<String>) -> Result<()> {
+    let author_client = create_client_without_signer(author_relays).await?;
+    if let Err(e) = author_client.send_event(event).await {
+        eprintln!("Unable to send the reply to the author's relays: {e}");
+    }
+    author_client.disconnect().await?;
     Ok(())
 }
+
+// Builds the marked e-tags and the p-tags of a reply to the given note. Also returns the read
+// relays of the parent's author, if they were asked for.
+async fn reply_tags(
+    client: &Client,
+    reply_to: &str,
+    to_author_relays: bool,
+) -> Result<(Vec<Tag>, Vec<String>)> {
+    let (parent_id, relay_hints) = parse_event_id_with_hints(reply_to).await?;
+    add_relay_hints(client, relay_hints.clone()).await;
+    let parent = fetch_event(client, parent_id, Some(FETCH_TIMEOUT))
+        .await?
+        .ok_or("Unable to find the note to reply to")?;
+
+    let parent_relay = event_relay_hint(client, parent.id, &relay_hints).await?;
+
+    let mut tags: Vec<Tag> = Vec::new();
+    match nip10_references(&parent) {
+        (Some(root), _) if root != parent.id => {
+            // Reuse the relay hint the parent has for the root
+            let root_relay = parent
+                .tags
+                .iter()
+                .map(|tag| tag.as_vec())
+                .find(|values| values.len() >= 3 && values[0] == "e" && values[1] == root.to_hex())
+                .map(|values| values[2].clone())
+                .unwrap_or_default();
+            tags.push(marked_event_tag(root, root_relay, "root"));
+            tags.push(marked_event_tag(parent.id, parent_relay, "reply"));
+        }
+        // The parent starts the thread
+        _ => tags.push(marked_event_tag(parent.id, parent_relay, "root")),
+    }
+
+    // Notify the parent's author and everyone the parent notified
+    let mut public_keys: Vec<PublicKey> = vec![parent.pubkey];
+    for values in parent.tags.iter().map(|tag| tag.as_vec()) {
+        if values.len() < 2 || values[0] != "p" {
+            continue;
+        }
+        if let Ok(public_key) = PublicKey::from_hex(&values[1]) {
+            if !public_keys.contains(&public_key) {
+                public_keys.push(public_key);
+            }
+        }
+    }
+    tags.extend(public_keys.into_iter().map(Tag::public_key));
+
+    let mut read_relays: Vec<String> = Vec::new();
+    if to_author_relays {
+        read_relays = fetch_relay_list(client, parent.pubkey, Some(FETCH_TIMEOUT))
+            .await?
+            .into_iter()
+            .filter(|(_, marker)| marker.as_deref() != Some("write"))
+            .map(|(url, _)| url)
+            .collect();
+    }
+
+    Ok((tags, read_relays))
+}
+
+// Returns the nevent to append to the content and the tags of a quoted event
+async fn quote_reference(client: &Client, quote: &str) -> Result<(String, Vec<Tag>)> {
+    let (event_id, relay_hints) = parse_event_id_with_hints(quote).await?;
+    add_relay_hints(client, relay_hints.clone()).await;
+    let quoted = fetch_event(client, event_id, Some(FETCH_TIMEOUT)).await?;
+    let relay = event_relay_hint(client, event_id, &relay_hints).await?;
+
+    let mut tags = vec![quote_tag(event_id, relay.clone())];
+    let mut reference = Nip19Event::new(event_id, [relay].into_iter().filter(|r| !r.is_empty()));
+    // Notify the author, if the quoted event could be found
+    if let Some(quoted) = quoted {
+        reference.author = Some(quoted.pubkey);
+        tags.push(Tag::public_key(quoted.pubkey));
+    }
+
+    Ok((reference.to_bech32()?, tags))
+}
+
+fn push_unique(tags: &mut Vec<Tag>, tag: Tag) {
+    if !tags.contains(&tag) {
+        tags.push(tag);
+    }
+}
+
+// Labels the note with a value in the namespace (NIP-32)
+fn label_tags(namespace: &str, value: &str) -> Vec<Tag> {
+    vec![
+        Tag::from_standardized_without_cell(TagStandard::LabelNamespace(namespace.to_string())),
+        Tag::from_standardized_without_cell(TagStandard::Label(vec![
+            value.to_string(),
+            namespace.to_string(),
+        ])),
+    ]
+}
+
+// Namespace and label tags for the language and the --label arguments, without duplicates
+fn self_label_tags(language: Option<&str>, labels: &[String]) -> Result<Vec<Tag>> {
+    let mut tags: Vec<Tag> = Vec::new();
+    if let Some(language) = language {
+        if language.len() != 2 || !language.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(format!(
+                "Invalid language '{language}', expected an ISO-639-1 code such as en"
+            )
+            .into());
+        }
+        for tag in label_tags("ISO-639-1", language) {
+            push_unique(&mut tags, tag);
+        }
+    }
+    for label in labels.iter() {
+        let label_tags = match label.split_once('=') {
+            Some((namespace, value)) => label_tags(namespace, value),
+            // Labels without a namespace are user generated content
+            None => vec![Tag::from_standardized_without_cell(TagStandard::Label(
+                vec![label.clone(), String::from("ugc")],
+            ))],
+        };
+        for tag in label_tags {
+            push_unique(&mut tags, tag);
+        }
+    }
+    Ok(tags)
+}
+
+// Turns plain @npub and @nprofile mentions into nostr: URIs. Only mentions that start a word
+// are rewritten, so URLs and email addresses are left alone.
+fn rewrite_mentions(content: &str) -> String {
+    let mention = Regex::new(r"(^|[\s(\[])@((?:npub|nprofile)1[02-9ac-hj-np-z]+)").unwrap();
+    mention.replace_all(content, "${1}nostr:${2}").into_owned()
+}
+
+// Tags for the nostr: URIs and hashtags in the content
+fn content_tags(content: &str) -> Vec<Tag> {
+    let mut tags: Vec<Tag> = Vec::new();
+    for (_, entity) in find_nostr_uris(content) {
+        match entity {
+            Nip19::Pubkey(public_key) => tags.push(Tag::public_key(public_key)),
+            Nip19::Profile(profile) => tags.push(Tag::public_key(profile.public_key)),
+            Nip19::EventId(event_id) => tags.push(quote_tag(event_id, String::new())),
+            Nip19::Event(event) => {
+                let relay = event.relays.first().map(|r| r.to_string());
+                tags.push(quote_tag(event.event_id, relay.unwrap_or_default()));
+                if let Some(author) = event.author {
+                    tags.push(Tag::public_key(author));
+                }
+            }
+            Nip19::Coordinate(coordinate) => {
+                tags.push(Tag::public_key(coordinate.public_key));
+                tags.push(Tag::coordinate(coordinate));
+            }
+            _ => {}
+        }
+    }
+
+    let hashtag = Regex::new(r"(?:^|\s)#(\w+)").unwrap();
+    for captures in hashtag.captures_iter(content) {
+        // Numbers such as "issue #123" aren't hashtags
+        if captures[1].chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        tags.push(Tag::hashtag(captures[1].to_lowercase()));
+    }
+
+    tags
+}
+
+fn quote_tag(event_id: EventId, relay: String) -> Tag {
+    Tag::custom(
+        TagKind::SingleLetter(SingleLetterTag::lowercase(Alphabet::Q)),
+        vec![event_id.to_hex(), relay],
+    )
+}
+
+fn marked_event_tag(event_id: EventId, relay: String, marker: &str) -> Tag {
+    Tag::custom(
+        TagKind::SingleLetter(SingleLetterTag::lowercase(Alphabet::E)),
+        vec![event_id.to_hex(), relay, marker.to_string()],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NPUB: &str = "npub1ktt8phjnkfmfrsxrgqpztdjuxk3x6psf80xyray0l3c7pyrln49qhkyhz0";
+    const PUBKEY: &str = "b2d670de53b27691c0c3400225b65c35a26d06093bcc41f48ffc71e0907f9d4a";
+    const EVENT_ID: &str = "bd6b4e7f35967cfb7acc6b7abc9ec185d496ea7ef6619639d4bed0dea80e8425";
+
+    #[test]
+    fn test_self_label_tags() {
+        let labels = |language: Option<&str>, labels: &[&str]| {
+            let labels: Vec<String> = labels.iter().map(|label| label.to_string()).collect();
+            self_label_tags(language, &labels).map(|tags| {
+                tags.into_iter()
+                    .map(|tag| tag.as_vec().to_vec())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(
+            labels(Some("en"), &["ISO-639-1=en", "com.example=meme", "funny"]).unwrap(),
+            vec![
+                vec!["L", "ISO-639-1"],
+                vec!["l", "en", "ISO-639-1"],
+                vec!["L", "com.example"],
+                vec!["l", "meme", "com.example"],
+                vec!["l", "funny", "ugc"],
+            ]
+        );
+        // Several labels in one namespace only add the namespace once
+        assert_eq!(
+            labels(None, &["com.example=a", "com.example=b", "com.example=a"]).unwrap(),
+            vec![
+                vec!["L", "com.example"],
+                vec!["l", "a", "com.example"],
+                vec!["l", "b", "com.example"],
+            ]
+        );
+
+        for language in ["EN", "eng", "e", "", "e1", "en-US"] {
+            assert!(labels(Some(language), &[]).is_err(), "{language}");
+        }
+    }
+
+    #[test]
+    fn test_rewrite_mentions() {
+        assert_eq!(
+            rewrite_mentions(&format!("gm @{NPUB}!")),
+            format!("gm nostr:{NPUB}!")
+        );
+        assert_eq!(
+            rewrite_mentions(&format!("@{NPUB} (@{NPUB})")),
+            format!("nostr:{NPUB} (nostr:{NPUB})")
+        );
+
+        // Already a URI, part of a URL or of an email address
+        for content in [
+            format!("nostr:{NPUB}"),
+            format!("https://example.com/@{NPUB}"),
+            format!("https://example.com/?user=@{NPUB}"),
+            format!("alice@{NPUB}.example.com"),
+            String::from("@npub is not a key"),
+        ] {
+            assert_eq!(rewrite_mentions(&content), content);
+        }
+    }
+
+    #[test]
+    fn test_content_tags() {
+        let public_key = PublicKey::from_hex(PUBKEY).unwrap();
+        let event_id = EventId::from_hex(EVENT_ID).unwrap();
+        let nevent = Nip19Event::new(event_id, ["wss://relay.example.com"])
+            .author(public_key)
+            .to_bech32()
+            .unwrap();
+        let coordinate = Coordinate::new(Kind::LongFormTextNote, public_key).identifier("article");
+        let naddr = coordinate.to_bech32().unwrap();
+
+        let content =
+            format!("nostr:{NPUB} wrote nostr:{nevent} and nostr:{naddr} #Nostr #rust2024 #nostr");
+        let tags = content_tags(&content);
+        let coordinate = Tag::coordinate(coordinate);
+        assert_eq!(
+            tags,
+            vec![
+                Tag::public_key(public_key),
+                quote_tag(event_id, String::from("wss://relay.example.com")),
+                Tag::public_key(public_key),
+                Tag::public_key(public_key),
+                coordinate,
+                Tag::hashtag("nostr"),
+                Tag::hashtag("rust2024"),
+                Tag::hashtag("nostr"),
+            ]
+        );
+
+        // Issue numbers and anchors in URLs aren't hashtags
+        assert!(content_tags("see issue #123 and https://example.com/#intro").is_empty());
+    }
+
+    #[test]
+    fn test_content_tags_dedup_with_quote() {
+        // What quote_reference returns for an event found on a relay
+        let public_key = PublicKey::from_hex(PUBKEY).unwrap();
+        let event_id = EventId::from_hex(EVENT_ID).unwrap();
+        let relay = String::from("wss://relay.example.com/");
+        let mut reference = Nip19Event::new(event_id, [relay.clone()]);
+        reference.author = Some(public_key);
+        let mut tags = vec![quote_tag(event_id, relay), Tag::public_key(public_key)];
+
+        let content = format!("so true\n\nnostr:{}", reference.to_bech32().unwrap());
+        for tag in content_tags(&content) {
+            push_unique(&mut tags, tag);
+        }
+        assert_eq!(tags.len(), 2);
+    }
+}
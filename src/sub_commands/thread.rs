@@ -0,0 +1,273 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use clap::Args;
+use nostr_sdk::prelude::*;
+
+use crate::sub_commands::list_events::add_relay_hints;
+use crate::timeline::Timeline;
+use crate::utils::{create_client, fetch_event, nip10_references, parse_event_id_with_hints};
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
+pub enum ThreadFormat {
+    /// Indented conversation tree
+    Tree,
+    /// Nested JSON objects with the event and its replies
+    Json,
+}
+
+#[derive(Args)]
+pub struct ThreadSubCommand {
+    /// Any note in the thread. Hex, note and nevent are supported.
+    event: String,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = ThreadFormat::Tree)]
+    format: ThreadFormat,
+    /// Timeout in seconds
+    #[arg(long, default_value_t = 10)]
+    timeout: u64,
+}
+
+pub async fn thread(relays: Vec<String>, sub_command_args: &ThreadSubCommand) -> Result<()> {
+    if relays.is_empty() {
+        panic!("No relays specified, at least one relay is required!")
+    }
+
+    let (event_id, relay_hints) = parse_event_id_with_hints(&sub_command_args.event).await?;
+
+    let client = create_client(&Keys::generate(), relays, 0).await?;
+    add_relay_hints(&client, relay_hints).await;
+
+    let timeout = Some(Duration::from_secs(sub_command_args.timeout));
+
+    let start = fetch_event(&client, event_id, timeout)
+        .await?
+        .ok_or("Unable to find the event")?;
+    let (thread_root, _) = nip10_references(&start);
+    let mut events: HashMap<EventId, Event> = HashMap::new();
+
+    // Walk up the parents until the top of the thread
+    let mut top = start;
+    while let (_, Some(parent)) = nip10_references(&top) {
+        if parent == top.id || events.contains_key(&parent) {
+            break;
+        }
+        match fetch_event(&client, parent, timeout).await? {
+            Some(parent_event) => {
+                events.insert(top.id, top);
+                top = parent_event;
+            }
+            None => {
+                eprintln!("Unable to find parent event {}", parent.to_bech32()?);
+                break;
+            }
+        }
+    }
+    let mut top_id = top.id;
+    events.insert(top.id, top);
+
+    // The chain can be broken by a missing event, fall back to the root marked in the tags
+    if let Some(thread_root) = thread_root.filter(|root| !events.contains_key(root)) {
+        if let Some(root_event) = fetch_event(&client, thread_root, timeout).await? {
+            top_id = root_event.id;
+            events.insert(root_event.id, root_event);
+        }
+    }
+
+    // Fetch replies to every known event until no new ones show up
+    let mut queried: HashSet<EventId> = HashSet::new();
+    loop {
+        let pending: Vec<EventId> = events
+            .keys()
+            .filter(|id| !queried.contains(id))
+            .copied()
+            .collect();
+        if pending.is_empty() {
+            break;
+        }
+        queried.extend(pending.iter().copied());
+
+        let filter = Filter::new().kind(Kind::TextNote).events(pending);
+        let candidates = client.get_events_of(vec![filter], timeout).await?;
+        add_thread_replies(&mut events, candidates);
+    }
+
+    // Attach every event to its parent. Only the events found while walking up can have a
+    // missing parent, they are attached to the root.
+    let mut replies: HashMap<EventId, Vec<&Event>> = HashMap::new();
+    for event in events.values().filter(|event| event.id != top_id) {
+        let parent = thread_parent(event, &events).unwrap_or(top_id);
+        replies.entry(parent).or_default().push(event);
+    }
+    for children in replies.values_mut() {
+        children.sort_by_key(|event| event.created_at);
+    }
+
+    let top = &events[&top_id];
+    let mut visited: HashSet<EventId> = HashSet::new();
+    match sub_command_args.format {
+        ThreadFormat::Tree => {
+            let all_events: Vec<Event> = events.values().cloned().collect();
+            let mut timeline = Timeline::default();
+            timeline
+                .resolve_profiles(&client, &all_events, timeout)
+                .await?;
+            let known_events: HashMap<EventId, &Event> =
+                events.iter().map(|(id, event)| (*id, event)).collect();
+            let mut lines: Vec<String> = Vec::new();
+            render_tree(
+                top,
+                0,
+                &replies,
+                &timeline,
+                &known_events,
+                &mut visited,
+                &mut lines,
+            );
+            println!("{}", lines.join("\n"));
+        }
+        ThreadFormat::Json => {
+            let tree = json_tree(top, &replies, &mut visited)?;
+            println!("{}", serde_json::to_string_pretty(&tree)?);
+        }
+    }
+
+    Ok(())
+}
+
+// The event of the thread a note replies to, preferring its direct parent over the root
+fn thread_parent(event: &Event, events: &HashMap<EventId, Event>) -> Option<EventId> {
+    let (root, parent) = nip10_references(event);
+    [parent, root]
+        .into_iter()
+        .flatten()
+        .find(|id| *id != event.id && events.contains_key(id))
+}
+
+// Adds the notes that reply to an event of the thread. Notes that only mention a thread event,
+// or that belong to another thread, also match the e-tag query and are dropped.
+fn add_thread_replies(events: &mut HashMap<EventId, Event>, candidates: Vec<Event>) {
+    let mut candidates: Vec<Event> = candidates
+        .into_iter()
+        .filter(|candidate| !events.contains_key(&candidate.id))
+        .collect();
+    // A reply can point to another reply of the same batch, so repeat until nothing is added
+    loop {
+        let (replies, others): (Vec<Event>, Vec<Event>) = candidates
+            .into_iter()
+            .partition(|candidate| thread_parent(candidate, events).is_some());
+        if replies.is_empty() {
+            break;
+        }
+        for reply in replies.into_iter() {
+            events.insert(reply.id, reply);
+        }
+        candidates = others;
+    }
+}
+
+fn render_tree(
+    event: &Event,
+    depth: usize,
+    replies: &HashMap<EventId, Vec<&Event>>,
+    timeline: &Timeline,
+    known_events: &HashMap<EventId, &Event>,
+    visited: &mut HashSet<EventId>,
+    lines: &mut Vec<String>,
+) {
+    if !visited.insert(event.id) {
+        return;
+    }
+    let indent = "│   ".repeat(depth);
+    for line in timeline.render(event, known_events).lines() {
+        lines.push(format!("{indent}{line}"));
+    }
+    lines.push(indent.trim_end().to_string());
+    for reply in replies.get(&event.id).into_iter().flatten() {
+        render_tree(
+            reply,
+            depth + 1,
+            replies,
+            timeline,
+            known_events,
+            visited,
+            lines,
+        );
+    }
+}
+
+fn json_tree(
+    event: &Event,
+    replies: &HashMap<EventId, Vec<&Event>>,
+    visited: &mut HashSet<EventId>,
+) -> Result<Value> {
+    visited.insert(event.id);
+    let mut children: Vec<Value> = Vec::new();
+    for reply in replies.get(&event.id).into_iter().flatten() {
+        if !visited.contains(&reply.id) {
+            children.push(json_tree(reply, replies, visited)?);
+        }
+    }
+    Ok(serde_json::json!({
+        "event": serde_json::to_value(event)?,
+        "replies": children,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(keys: &Keys, content: &str, tags: Vec<Vec<String>>) -> Event {
+        let tags: Vec<Tag> = tags.iter().map(|tag| Tag::parse(tag).unwrap()).collect();
+        EventBuilder::text_note(content, tags)
+            .to_event(keys)
+            .unwrap()
+    }
+
+    fn e_tag(event: &Event, marker: &str) -> Vec<String> {
+        vec![
+            String::from("e"),
+            event.id.to_hex(),
+            String::new(),
+            marker.to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_add_thread_replies() {
+        let keys = Keys::generate();
+        let root = note(&keys, "root", vec![]);
+        let other_root = note(&keys, "another thread", vec![]);
+        let reply = note(&keys, "reply", vec![e_tag(&root, "root")]);
+        let nested = note(
+            &keys,
+            "nested",
+            vec![e_tag(&root, "root"), e_tag(&reply, "reply")],
+        );
+        let mention = note(&keys, "mention", vec![e_tag(&root, "mention")]);
+        let elsewhere = note(
+            &keys,
+            "elsewhere",
+            vec![e_tag(&other_root, "root"), e_tag(&root, "mention")],
+        );
+
+        let mut events: HashMap<EventId, Event> = HashMap::new();
+        events.insert(root.id, root.clone());
+        // The nested reply comes before its parent
+        add_thread_replies(
+            &mut events,
+            vec![nested.clone(), mention, elsewhere, reply.clone()],
+        );
+
+        let mut ids: Vec<EventId> = events.keys().copied().collect();
+        ids.sort();
+        let mut expected = vec![root.id, reply.id, nested.id];
+        expected.sort();
+        assert_eq!(ids, expected);
+
+        assert_eq!(thread_parent(&reply, &events), Some(root.id));
+        assert_eq!(thread_parent(&nested, &events), Some(reply.id));
+        assert_eq!(thread_parent(&root, &events), None);
+    }
+}
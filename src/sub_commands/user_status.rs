@@ -1,14 +1,16 @@
-use std::ops::Add;
 use std::str::FromStr;
-use std::time::Duration;
 
 use clap::Args;
 use nostr_sdk::prelude::*;
 use nostr_sdk::TagKind::SingleLetter;
+use serde::Deserialize;
 
-use crate::utils::{create_client, parse_key_or_id_to_hex_string, parse_private_key};
+use crate::utils::{
+    create_client, deserialize_expiration, parse_expiration, parse_key_or_id_to_hex_string,
+    parse_private_key,
+};
 
-#[derive(Args)]
+#[derive(Args, Deserialize)]
 pub struct UserStatusSubCommand {
     /// Text note content
     #[arg(short, long)]
@@ -25,11 +27,13 @@ pub struct UserStatusSubCommand {
     /// Reference tag
     #[arg(short, long)]
     rtag: Option<String>,
-    /// Seconds till expiration (NIP-40)
+    /// Expiration (NIP-40). Seconds from now, a duration such as 2h or 7d, or an ISO-8601 date.
     #[arg(long)]
-    expiration: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_expiration")]
+    expiration: Option<String>,
     // Print keys as hex
     #[arg(long, default_value = "false")]
+    #[serde(default)]
     hex: bool,
 }
 
@@ -46,6 +50,22 @@ pub async fn set_user_status(
     let keys = parse_private_key(private_key, true).await?;
     let client = create_client(&keys, relays, difficulty_target).await?;
 
+    let event_id = send_user_status(&client, &keys, difficulty_target, sub_command_args).await?;
+    if !sub_command_args.hex {
+        println!("Published user status with id: {}", event_id.to_bech32()?);
+    } else {
+        println!("Published user status with id: {}", event_id.to_hex());
+    }
+
+    Ok(())
+}
+
+pub async fn send_user_status(
+    client: &Client,
+    keys: &Keys,
+    difficulty_target: u8,
+    sub_command_args: &UserStatusSubCommand,
+) -> Result<EventId> {
     // Set up tags
     let mut tags: Vec<Tag> = vec![];
 
@@ -56,9 +76,8 @@ pub async fn set_user_status(
     }
 
     // Add expiration tag
-    if let Some(expiration) = sub_command_args.expiration {
-        let timestamp = Timestamp::now().add(Duration::from_secs(expiration));
-        tags.push(Tag::expiration(timestamp));
+    if let Some(expiration) = &sub_command_args.expiration {
+        tags.push(Tag::expiration(parse_expiration(expiration)?));
     }
 
     // Add p-tag
@@ -85,14 +104,9 @@ pub async fn set_user_status(
 
     // Publish event
     let event = EventBuilder::new(Kind::Custom(30315), sub_command_args.content.clone(), tags)
-        .to_pow_event(&keys, difficulty_target)?;
+        .to_pow_event(keys, difficulty_target)?;
 
     let event_id = client.send_event(event).await?;
-    if !sub_command_args.hex {
-        println!("Published user status with id: {}", event_id.to_bech32()?);
-    } else {
-        println!("Published user status with id: {}", event_id.to_hex());
-    }
 
-    Ok(())
+    Ok(event_id)
 }
@@ -0,0 +1,170 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use nostr_sdk::prelude::*;
+
+use crate::utils::{find_nostr_uris, nip10_references};
+
+// Renders events as a human readable timeline with author names resolved from kind 0 events
+#[derive(Default)]
+pub struct Timeline {
+    names: HashMap<PublicKey, String>,
+    resolved: HashSet<PublicKey>,
+}
+
+impl Timeline {
+    // Fetches the metadata of every author and mentioned public key that hasn't been resolved yet
+    pub async fn resolve_profiles(
+        &mut self,
+        client: &Client,
+        events: &[Event],
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        let mut pubkeys: HashSet<PublicKey> = HashSet::new();
+        for event in events.iter() {
+            pubkeys.insert(event.pubkey);
+            for (_, entity) in find_nostr_uris(&event.content) {
+                match entity {
+                    Nip19::Pubkey(public_key) => {
+                        pubkeys.insert(public_key);
+                    }
+                    Nip19::Profile(profile) => {
+                        pubkeys.insert(profile.public_key);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        pubkeys.retain(|public_key| !self.resolved.contains(public_key));
+        if pubkeys.is_empty() {
+            return Ok(());
+        }
+
+        let filter = Filter::new().authors(pubkeys.clone()).kind(Kind::Metadata);
+        let mut metadata_events = client.get_events_of(vec![filter], timeout).await?;
+        // Oldest first so the newest metadata of each author wins
+        metadata_events.sort_by_key(|event| event.created_at);
+        for event in metadata_events.iter() {
+            if let Ok(metadata) = Metadata::from_json(&event.content) {
+                let name = metadata
+                    .display_name
+                    .filter(|name| !name.trim().is_empty())
+                    .or(metadata.name.filter(|name| !name.trim().is_empty()));
+                if let Some(name) = name {
+                    self.names.insert(event.pubkey, name);
+                }
+            }
+        }
+        self.resolved.extend(pubkeys);
+
+        Ok(())
+    }
+
+    pub fn render(&self, event: &Event, known_events: &HashMap<EventId, &Event>) -> String {
+        let mut lines: Vec<String> = vec![format!(
+            "{} · {} · {}",
+            self.display_name(&event.pubkey),
+            kind_name(event.kind),
+            relative_time(event.created_at, Timestamp::now())
+        )];
+
+        let (_, parent) = nip10_references(event);
+        if let Some(parent) = parent {
+            let parent_description = match known_events.get(&parent) {
+                Some(parent_event) => format!(
+                    "@{} {}",
+                    self.display_name(&parent_event.pubkey),
+                    short_bech32(&parent)
+                ),
+                None => short_bech32(&parent),
+            };
+            lines.push(format!("  ↳ reply to {parent_description}"));
+        }
+
+        for line in self.expand_mentions(&event.content).lines() {
+            lines.push(format!("  {line}"));
+        }
+        lines.push(format!("  {}", event.id.to_bech32().unwrap_or_default()));
+
+        lines.join("\n")
+    }
+
+    fn display_name(&self, public_key: &PublicKey) -> String {
+        match self.names.get(public_key) {
+            Some(name) => name.clone(),
+            None => short_bech32(public_key),
+        }
+    }
+
+    // Replaces nostr: URIs with the profile name or a shortened bech32 entity
+    fn expand_mentions(&self, content: &str) -> String {
+        let mut expanded = String::with_capacity(content.len());
+        let mut last_end = 0;
+        for (range, entity) in find_nostr_uris(content) {
+            expanded.push_str(&content[last_end..range.start]);
+            let replacement = match entity {
+                Nip19::Pubkey(public_key) => format!("@{}", self.display_name(&public_key)),
+                Nip19::Profile(profile) => format!("@{}", self.display_name(&profile.public_key)),
+                Nip19::EventId(event_id) => short_bech32(&event_id),
+                Nip19::Event(event) => short_bech32(&event.event_id),
+                Nip19::Coordinate(coordinate) => {
+                    format!("[{} {}]", kind_name(coordinate.kind), coordinate.identifier)
+                }
+                _ => content[range.clone()].to_string(),
+            };
+            expanded.push_str(&replacement);
+            last_end = range.end;
+        }
+        expanded.push_str(&content[last_end..]);
+        expanded
+    }
+}
+
+fn short_bech32<T: ToBech32>(entity: &T) -> String {
+    match entity.to_bech32() {
+        Ok(bech32) if bech32.len() > 16 => format!("{}…", &bech32[..16]),
+        Ok(bech32) => bech32,
+        Err(_) => String::from("?"),
+    }
+}
+
+fn kind_name(kind: Kind) -> String {
+    let name = match kind.as_u32() {
+        0 => "metadata",
+        1 => "note",
+        3 => "contacts",
+        4 => "direct message",
+        5 => "deletion",
+        6 => "repost",
+        7 => "reaction",
+        8 => "badge award",
+        16 => "generic repost",
+        40 => "channel creation",
+        41 => "channel metadata",
+        42 => "channel message",
+        43 => "channel hide message",
+        44 => "channel mute user",
+        1984 => "report",
+        9734 => "zap request",
+        9735 => "zap",
+        10000 => "mute list",
+        10002 => "relay list",
+        30008 => "profile badges",
+        30009 => "badge definition",
+        30023 => "article",
+        30315 => "user status",
+        other => return format!("kind {other}"),
+    };
+    name.to_string()
+}
+
+fn relative_time(created_at: Timestamp, now: Timestamp) -> String {
+    let seconds = now.as_u64().saturating_sub(created_at.as_u64());
+    match seconds {
+        0..=59 => String::from("just now"),
+        60..=3_599 => format!("{}m ago", seconds / 60),
+        3_600..=86_399 => format!("{}h ago", seconds / 3_600),
+        86_400..=604_799 => format!("{}d ago", seconds / 86_400),
+        _ => created_at.to_human_datetime(),
+    }
+}
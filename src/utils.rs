@@ -1,5 +1,10 @@
+use std::borrow::Cow;
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::str::FromStr;
 use std::time::Duration;
 
+use nostr_sdk::nostr::nips::nip05;
 use nostr_sdk::prelude::*;
 
 pub async fn parse_private_key(private_key: Option<String>, print_keys: bool) -> Result<Keys> {
@@ -35,16 +40,94 @@ pub async fn parse_private_key(private_key: Option<String>, print_keys: bool) ->
 
 // Creates the websocket client that is used for communicating with relays
 pub async fn create_client(keys: &Keys, relays: Vec<String>, difficulty: u8) -> Result<Client> {
-    let opts = Options::new()
-        .send_timeout(Some(Duration::from_secs(15)))
-        .wait_for_send(true)
-        .difficulty(difficulty);
-    let client = Client::with_opts(keys, opts);
+    let client = Client::with_opts(keys, client_options(difficulty));
+    client.add_relays(relays).await?;
+    client.connect().await;
+    Ok(client)
+}
+
+// Creates a client without keys, for commands that only relay events signed by others
+pub async fn create_client_without_signer(relays: Vec<String>) -> Result<Client> {
+    let client = ClientBuilder::new().opts(client_options(0)).build();
     client.add_relays(relays).await?;
     client.connect().await;
     Ok(client)
 }
 
+fn client_options(difficulty: u8) -> Options {
+    Options::new()
+        .send_timeout(Some(Duration::from_secs(15)))
+        .wait_for_send(true)
+        .difficulty(difficulty)
+}
+
+// Resolves the content of an event from the command line, a file (- for stdin) or the user's
+// editor. The editor starts with whatever content was given, returns None if nothing was.
+pub fn read_content(
+    content: Option<&str>,
+    content_file: Option<&str>,
+    edit: bool,
+) -> Result<Option<String>> {
+    let content = match (content, content_file) {
+        (Some(_), Some(_)) => return Err("Content and a content file can't be combined".into()),
+        (Some(content), None) => Some(content.to_string()),
+        (None, Some("-")) => {
+            let mut content = String::new();
+            std::io::stdin().read_to_string(&mut content)?;
+            Some(content)
+        }
+        (None, Some(path)) => Some(std::fs::read_to_string(path)?),
+        (None, None) => None,
+    };
+    if !edit {
+        return Ok(content);
+    }
+
+    let (path, mut file) = create_editor_file()?;
+    file.write_all(content.unwrap_or_default().as_bytes())?;
+    drop(file);
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    // The editor can come with arguments, e.g. "code --wait"
+    let mut editor_parts = editor.split_whitespace();
+    let program = editor_parts.next().ok_or("No editor configured")?;
+    let status = std::process::Command::new(program)
+        .args(editor_parts)
+        .arg(&path)
+        .status();
+    let edited = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    if !status?.success() {
+        return Err(format!("{editor} exited with an error").into());
+    }
+
+    // Editors usually end the file with a newline
+    let edited = edited?.trim_end_matches('\n').to_string();
+    if edited.trim().is_empty() {
+        return Err("Aborting, the content is empty".into());
+    }
+    Ok(Some(edited))
+}
+
+// Creates the file the editor works on. The name is random and an existing file is never
+// opened, so nothing placed in the temp directory beforehand (e.g. a symlink) gets written to.
+fn create_editor_file() -> Result<(std::path::PathBuf, std::fs::File)> {
+    loop {
+        let name = format!("nostr-tool-{:016x}.md", rand::random::<u64>());
+        let path = std::env::temp_dir().join(name);
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        match options.open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
 pub async fn parse_key_or_id_to_hex_string(
     input: String,
 ) -> Result<String, Box<dyn std::error::Error>> {
@@ -67,6 +150,396 @@ pub async fn parse_key_or_id_to_hex_string(
     Ok(hex_key_or_id)
 }
 
+// Decodes a NIP-19 entity, optionally prefixed with `nostr:`, or looks up a NIP-05 identifier
+async fn parse_nostr_entity(input: &str) -> Result<Nip19> {
+    let input = input.strip_prefix("nostr:").unwrap_or(input);
+    if input.contains('@') || input.contains('.') {
+        let profile = nip05::get_profile(input, None).await?;
+        return Ok(Nip19::Profile(profile));
+    }
+    Ok(Nip19::from_bech32(input)?)
+}
+
+// Resolves a public key given as hex, npub, nprofile, naddr (the author) or NIP-05 identifier.
+// Any relay hints embedded in the entity are returned alongside the key.
+pub async fn parse_public_key_with_hints(input: &str) -> Result<(PublicKey, Vec<String>)> {
+    if let Ok(public_key) = PublicKey::from_hex(input) {
+        return Ok((public_key, Vec::new()));
+    }
+    match parse_nostr_entity(input).await? {
+        Nip19::Pubkey(public_key) => Ok((public_key, Vec::new())),
+        Nip19::Profile(profile) => Ok((
+            profile.public_key,
+            profile.relays.iter().map(|r| r.to_string()).collect(),
+        )),
+        Nip19::Coordinate(coordinate) => Ok((coordinate.public_key, coordinate.relays)),
+        _ => Err(format!("{input} is not a public key").into()),
+    }
+}
+
+// Resolves an event id given as hex, note or nevent. Any relay hints embedded in the entity
+// are returned alongside the id.
+pub async fn parse_event_id_with_hints(input: &str) -> Result<(EventId, Vec<String>)> {
+    if let Ok(event_id) = EventId::from_hex(input) {
+        return Ok((event_id, Vec::new()));
+    }
+    match parse_nostr_entity(input).await? {
+        Nip19::EventId(event_id) => Ok((event_id, Vec::new())),
+        Nip19::Event(event) => Ok((
+            event.event_id,
+            event.relays.iter().map(|r| r.to_string()).collect(),
+        )),
+        Nip19::Coordinate(_) => Err(format!(
+            "{input} references an addressable event, use an a-tag filter instead"
+        )
+        .into()),
+        _ => Err(format!("{input} is not an event id").into()),
+    }
+}
+
+// Parses a point in time used for --since and --until. Accepts unix timestamps, durations ago
+// such as `2h` or `7d`, ISO-8601 dates and date-times, and the keywords `now`, `today` and
+// `yesterday`. Dates without an offset are interpreted as UTC.
+pub fn parse_timestamp(input: &str) -> Result<Timestamp> {
+    parse_timestamp_at(input, Timestamp::now(), false)
+}
+
+// Parses a NIP-40 expiration. Same formats as `parse_timestamp`, except that plain numbers and
+// durations are counted forward from now, e.g. `3600` or `1h` expires in one hour.
+pub fn parse_expiration(input: &str) -> Result<Timestamp> {
+    parse_timestamp_at(input, Timestamp::now(), true)
+}
+
+// Reads an expiration from a batch script, where it can be a plain number of seconds as well as
+// any string `parse_expiration` understands
+pub fn deserialize_expiration<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Expiration {
+        Seconds(u64),
+        Text(String),
+    }
+
+    let expiration: Option<Expiration> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(expiration.map(|expiration| match expiration {
+        Expiration::Seconds(seconds) => seconds.to_string(),
+        Expiration::Text(text) => text,
+    }))
+}
+
+fn parse_timestamp_at(input: &str, now: Timestamp, forward: bool) -> Result<Timestamp> {
+    let input = input.trim();
+    let now = now.as_u64();
+    let start_of_today = now - now % 86_400;
+
+    let seconds = match input.to_lowercase().as_str() {
+        "now" => Some(now),
+        "today" => Some(start_of_today),
+        "yesterday" => start_of_today.checked_sub(86_400),
+        "tomorrow" => Some(start_of_today + 86_400),
+        _ => {
+            if let Ok(number) = input.parse::<u64>() {
+                if forward {
+                    Some(now + number)
+                } else {
+                    Some(number)
+                }
+            } else if let Some(duration) = parse_duration(input) {
+                if forward {
+                    Some(now + duration.as_secs())
+                } else {
+                    now.checked_sub(duration.as_secs())
+                }
+            } else {
+                parse_iso8601(input)
+            }
+        }
+    };
+
+    match seconds {
+        Some(seconds) => Ok(Timestamp::from(seconds)),
+        None => Err(format!(
+            "Invalid time '{input}', expected a unix timestamp, a duration such as 2h or 7d, an ISO-8601 date, today or yesterday"
+        )
+        .into()),
+    }
+}
+
+// Parses a duration such as `30s`, `15m`, `2h`, `7d` or `2w`
+fn parse_duration(input: &str) -> Option<Duration> {
+    let unit = input.chars().last()?;
+    let value: u64 = input[..input.len() - unit.len_utf8()].parse().ok()?;
+    let multiplier = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3_600,
+        'd' => 86_400,
+        'w' => 604_800,
+        _ => return None,
+    };
+    Some(Duration::from_secs(value.checked_mul(multiplier)?))
+}
+
+// Parses `YYYY-MM-DD` and `YYYY-MM-DDTHH:MM[:SS][Z|+HH:MM|-HH:MM]` into unix seconds
+fn parse_iso8601(input: &str) -> Option<u64> {
+    let (date, time) = match input.split_once(|c| c == 'T' || c == ' ') {
+        Some((date, time)) => (date, Some(time)),
+        None => (input, None),
+    };
+
+    let mut date_parts = date.split('-');
+    let year: i64 = date_parts.next()?.parse().ok()?;
+    let month: i64 = date_parts.next()?.parse().ok()?;
+    let day: i64 = date_parts.next()?.parse().ok()?;
+    if date_parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days since the unix epoch, see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let shifted_year = if month <= 2 { year - 1 } else { year };
+    let era = if shifted_year >= 0 {
+        shifted_year
+    } else {
+        shifted_year - 399
+    } / 400;
+    let year_of_era = shifted_year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let mut seconds = days * 86_400;
+    if let Some(time) = time {
+        let (time, offset) = if let Some(time) = time.strip_suffix('Z') {
+            (time, 0)
+        } else if let Some(position) = time.rfind(|c| c == '+' || c == '-') {
+            let sign = if time[position..].starts_with('-') {
+                -1
+            } else {
+                1
+            };
+            let (hours, minutes) = time[position + 1..].split_once(':')?;
+            let offset = hours.parse::<i64>().ok()? * 3_600 + minutes.parse::<i64>().ok()? * 60;
+            (&time[..position], sign * offset)
+        } else {
+            (time, 0)
+        };
+
+        let mut time_parts = time.split(':');
+        let hours: i64 = time_parts.next()?.parse().ok()?;
+        let minutes: i64 = time_parts.next()?.parse().ok()?;
+        let secs: i64 = match time_parts.next() {
+            // Fractional seconds are ignored
+            Some(secs) => secs.split('.').next()?.parse().ok()?,
+            None => 0,
+        };
+        if time_parts.next().is_some() || hours > 23 || minutes > 59 || secs > 60 {
+            return None;
+        }
+        seconds += hours * 3_600 + minutes * 60 + secs - offset;
+    }
+
+    u64::try_from(seconds).ok()
+}
+
+// Parses an a-tag coordinate given either as `kind:pubkey:d` or as a NIP-19 naddr
+pub fn parse_coordinate(input: &str) -> Result<Coordinate> {
+    let input = input.strip_prefix("nostr:").unwrap_or(input);
+    let coordinate = if input.starts_with("naddr") {
+        Coordinate::from_bech32(input)?
+    } else {
+        Coordinate::from_str(input)?
+    };
+    Ok(coordinate)
+}
+
+// Parses a `<letter>=<value>` tag filter, e.g. `t=bitcoin`. a-tag values are validated and normalized
+// to the `kind:pubkey:d` format.
+pub fn parse_tag_filter(input: &str) -> Result<(SingleLetterTag, String)> {
+    let (letter, value) = input
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid tag filter '{input}', expected <letter>=<value>"))?;
+    if value.is_empty() {
+        return Err(format!("Invalid tag filter '{input}', the value is empty").into());
+    }
+
+    let mut chars = letter.chars();
+    let character = match (chars.next(), chars.next()) {
+        (Some(character), None) => character,
+        _ => {
+            return Err(format!(
+                "Invalid tag filter '{input}', only single-letter tags can be filtered on"
+            )
+            .into())
+        }
+    };
+    let tag = SingleLetterTag::from_char(character)?;
+
+    if character == 'a' {
+        let coordinate = parse_coordinate(value)?;
+        return Ok((tag, coordinate.to_string()));
+    }
+
+    Ok((tag, value.to_string()))
+}
+
+// Finds every NIP-21 `nostr:` URI in the content, returning its byte range and the decoded entity
+pub fn find_nostr_uris(content: &str) -> Vec<(Range<usize>, Nip19)> {
+    let mut uris = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = content[offset..].find("nostr:") {
+        let start = offset + start;
+        let entity_start = start + "nostr:".len();
+        let entity_end = content[entity_start..]
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .map(|end| entity_start + end)
+            .unwrap_or(content.len());
+        if let Ok(entity) = Nip19::from_bech32(&content[entity_start..entity_end]) {
+            uris.push((start..entity_end, entity));
+        }
+        offset = entity_end;
+    }
+    uris
+}
+
+// Parses a tag given as the tag key followed by its values, all separated by '|', e.g.
+// "r|https://example.com"
+pub fn parse_custom_tag(input: &str) -> Tag {
+    let parts: Vec<String> = input.split('|').map(String::from).collect();
+    let tag_kind = parts[0].clone();
+    Tag::custom(TagKind::Custom(Cow::from(tag_kind)), parts[1..].to_vec())
+}
+
+// Fetches a single event by id, None if none of the relays has it
+pub async fn fetch_event(
+    client: &Client,
+    event_id: EventId,
+    timeout: Option<Duration>,
+) -> Result<Option<Event>> {
+    let events = client
+        .get_events_of(vec![Filter::new().id(event_id)], timeout)
+        .await?;
+    Ok(events.into_iter().find(|event| event.id == event_id))
+}
+
+// Picks a relay that is known to have the event, to be used as a relay hint in tags. The
+// relays the event was seen on are preferred over the given hints.
+pub async fn event_relay_hint(
+    client: &Client,
+    event_id: EventId,
+    relay_hints: &[String],
+) -> Result<String> {
+    let relay = client
+        .database()
+        .event_seen_on_relays(event_id)
+        .await?
+        .and_then(|urls| urls.into_iter().next())
+        .map(|url| url.to_string())
+        .or_else(|| relay_hints.first().cloned())
+        .unwrap_or_default();
+    Ok(relay)
+}
+
+// Fetches the latest relay list (NIP-65) of the public key, as relay urls with their optional
+// read or write marker
+pub async fn fetch_relay_list(
+    client: &Client,
+    public_key: PublicKey,
+    timeout: Option<Duration>,
+) -> Result<Vec<(String, Option<String>)>> {
+    let relay_lists = client
+        .get_events_of(
+            vec![Filter::new().author(public_key).kind(Kind::RelayList)],
+            timeout,
+        )
+        .await?;
+    let Some(relay_list) = relay_lists.iter().max_by_key(|event| event.created_at) else {
+        return Ok(Vec::new());
+    };
+
+    let relays = relay_list
+        .tags
+        .iter()
+        .map(|tag| tag.as_vec())
+        .filter(|values| values.len() >= 2 && values[0] == "r")
+        .map(|values| (values[1].clone(), values.get(2).cloned()))
+        .collect();
+    Ok(relays)
+}
+
+// Returns the thread root and the direct parent referenced by a reply's e-tags (NIP-10).
+// Marked tags are preferred, the deprecated positional scheme is used as a fallback.
+pub fn nip10_references(event: &Event) -> (Option<EventId>, Option<EventId>) {
+    let mut e_tags: Vec<(EventId, Option<&str>)> = Vec::new();
+    for tag in event.tags.iter() {
+        let values = tag.as_vec();
+        if values.len() < 2 || values[0] != "e" {
+            continue;
+        }
+        if let Ok(event_id) = EventId::from_hex(&values[1]) {
+            e_tags.push((event_id, values.get(3).map(|marker| marker.as_str())));
+        }
+    }
+
+    let marked = |marker: &str| {
+        e_tags
+            .iter()
+            .find(|(_, m)| *m == Some(marker))
+            .map(|(event_id, _)| *event_id)
+    };
+    let root = marked("root");
+    let reply = marked("reply");
+    if root.is_some() || reply.is_some() {
+        return (root.or(reply), reply.or(root));
+    }
+
+    // Positional: first e-tag is the root, last one is the reply, anything between is a mention.
+    // Tags explicitly marked as mentions never take part in the thread.
+    e_tags.retain(|(_, marker)| *marker != Some("mention"));
+    match (e_tags.first(), e_tags.last()) {
+        (Some((root, _)), Some((reply, _))) => (Some(*root), Some(*reply)),
+        _ => (None, None),
+    }
+}
+
+// Layout of a file with events, as written by list-events
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventsFileFormat {
+    // A single JSON array of events
+    Json,
+    // One JSON event per line
+    Jsonl,
+}
+
+// Reads events from a JSON array or JSON lines file. A missing file is treated as empty.
+pub fn read_events_file(path: &str) -> Result<(Vec<Event>, EventsFileFormat)> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    if content.trim_start().starts_with('[') {
+        let events: Vec<Event> = serde_json::from_str(&content)?;
+        return Ok((events, EventsFileFormat::Json));
+    }
+
+    let mut events = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = Event::from_json(line)
+            .map_err(|e| format!("{path}:{}: invalid event: {e}", number + 1))?;
+        events.push(event);
+    }
+    Ok((events, EventsFileFormat::Jsonl))
+}
+
 #[derive(clap::ValueEnum, Clone, Debug)]
 pub enum Prefix {
     Npub,
@@ -121,6 +594,143 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_custom_tag() {
+        let tag = parse_custom_tag("r|https://example.com|read");
+        assert_eq!(tag.as_vec(), ["r", "https://example.com", "read"]);
+
+        let tag = parse_custom_tag("content-warning");
+        assert_eq!(tag.as_vec(), ["content-warning"]);
+    }
+
+    #[test]
+    fn test_parse_tag_filter() {
+        let (tag, value) = parse_tag_filter("t=bitcoin").unwrap();
+        assert_eq!(tag, SingleLetterTag::from_char('t').unwrap());
+        assert_eq!(value, "bitcoin");
+
+        let coordinate =
+            "30023:b2d670de53b27691c0c3400225b65c35a26d06093bcc41f48ffc71e0907f9d4a:my-article";
+        let (tag, value) = parse_tag_filter(&format!("a={coordinate}")).unwrap();
+        assert_eq!(tag, SingleLetterTag::from_char('a').unwrap());
+        assert_eq!(value, coordinate);
+
+        assert!(parse_tag_filter("a=not-a-coordinate").is_err());
+        assert!(parse_tag_filter("title=hello").is_err());
+        assert!(parse_tag_filter("bitcoin").is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        // 2024-03-15T12:30:00Z
+        let now = Timestamp::from(1_710_505_800);
+
+        let parse = |input: &str| parse_timestamp_at(input, now, false).unwrap().as_u64();
+        assert_eq!(parse("1700000000"), 1_700_000_000);
+        assert_eq!(parse("2h"), 1_710_505_800 - 7_200);
+        assert_eq!(parse("7d"), 1_710_505_800 - 604_800);
+        assert_eq!(parse("today"), 1_710_460_800);
+        assert_eq!(parse("yesterday"), 1_710_374_400);
+        assert_eq!(parse("2024-03-15"), 1_710_460_800);
+        assert_eq!(parse("2024-03-15T12:30:00Z"), 1_710_505_800);
+        assert_eq!(parse("2024-03-15T14:30:00+02:00"), 1_710_505_800);
+        assert_eq!(parse("1970-01-01"), 0);
+
+        let expiration = |input: &str| parse_timestamp_at(input, now, true).unwrap().as_u64();
+        assert_eq!(expiration("3600"), 1_710_505_800 + 3_600);
+        assert_eq!(expiration("1d"), 1_710_505_800 + 86_400);
+        assert_eq!(expiration("2024-03-16"), 1_710_547_200);
+
+        assert!(parse_timestamp_at("last week", now, false).is_err());
+        assert!(parse_timestamp_at("2024-13-01", now, false).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_expiration() {
+        #[derive(serde::Deserialize)]
+        struct Operation {
+            #[serde(default, deserialize_with = "deserialize_expiration")]
+            expiration: Option<String>,
+        }
+        let expiration = |json: &str| {
+            serde_json::from_str::<Operation>(json)
+                .map(|operation| operation.expiration)
+                .map_err(|e| e.to_string())
+        };
+
+        assert_eq!(
+            expiration(r#"{"expiration": 3600}"#).unwrap().as_deref(),
+            Some("3600")
+        );
+        assert_eq!(
+            expiration(r#"{"expiration": "2h"}"#).unwrap().as_deref(),
+            Some("2h")
+        );
+        assert_eq!(expiration(r#"{"expiration": null}"#).unwrap(), None);
+        assert_eq!(expiration("{}").unwrap(), None);
+        assert!(expiration(r#"{"expiration": -1}"#).is_err());
+    }
+
+    #[test]
+    fn test_find_nostr_uris() {
+        let content = "gm nostr:npub1ktt8phjnkfmfrsxrgqpztdjuxk3x6psf80xyray0l3c7pyrln49qhkyhz0, see nostr:nope";
+        let uris = find_nostr_uris(content);
+
+        assert_eq!(uris.len(), 1);
+        assert_eq!(uris[0].0, 3..72);
+        assert_eq!(
+            uris[0].1,
+            Nip19::Pubkey(
+                PublicKey::from_hex(
+                    "b2d670de53b27691c0c3400225b65c35a26d06093bcc41f48ffc71e0907f9d4a"
+                )
+                .unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_nip10_references() {
+        let ids: Vec<String> = (1..=3).map(|i| format!("{i:064x}")).collect();
+        let id = |i: usize| EventId::from_hex(&ids[i]).unwrap();
+        let event_with = |tags: Vec<Vec<&str>>| {
+            let tags: Vec<Tag> = tags.iter().map(|tag| Tag::parse(tag).unwrap()).collect();
+            EventBuilder::new(Kind::TextNote, "", tags)
+                .to_event(&Keys::generate())
+                .unwrap()
+        };
+
+        // Marked, in any order
+        let event = event_with(vec![
+            vec!["e", &ids[2], "", "reply"],
+            vec!["e", &ids[1], "", "mention"],
+            vec!["e", &ids[0], "", "root"],
+        ]);
+        assert_eq!(nip10_references(&event), (Some(id(0)), Some(id(2))));
+
+        // A direct reply to the root only has the root marker
+        let event = event_with(vec![vec!["e", &ids[0], "", "root"]]);
+        assert_eq!(nip10_references(&event), (Some(id(0)), Some(id(0))));
+
+        // Positional, the mention in the middle is skipped
+        let event = event_with(vec![
+            vec!["e", &ids[0]],
+            vec!["e", &ids[1]],
+            vec!["e", &ids[2]],
+        ]);
+        assert_eq!(nip10_references(&event), (Some(id(0)), Some(id(2))));
+
+        // Marked mentions are not part of the positional thread
+        let event = event_with(vec![vec!["e", &ids[0]], vec!["e", &ids[1], "", "mention"]]);
+        assert_eq!(nip10_references(&event), (Some(id(0)), Some(id(0))));
+
+        let event = event_with(vec![vec!["e", &ids[1], "", "mention"]]);
+        assert_eq!(nip10_references(&event), (None, None));
+
+        let event = event_with(vec![]);
+        assert_eq!(nip10_references(&event), (None, None));
+    }
+
     #[tokio::test]
     async fn test_parse_bech32_private_key() {
         let bech32_encoded_key =